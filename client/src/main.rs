@@ -3,11 +3,43 @@ use notan::draw::*;
 use shared::*;
 use ewebsock::{WsEvent, WsMessage, WsReceiver, WsSender};
 
+const MAX_FRAMES_PER_DRAW: u32 = 5;
+const POP_EFFECT_TIME: f32 = 0.3;
+const PUZZLE_FILES: [&str; 5] = [
+    include_str!("../puzzles/01_first_chain.json"),
+    include_str!("../puzzles/02_clean_sweep.json"),
+    include_str!("../puzzles/03_red_harvest.json"),
+    include_str!("../puzzles/04_double_pop.json"),
+    include_str!("../puzzles/05_stairs.json"),
+];
+
+enum Screen {
+    Versus,
+    PuzzleSelect { selected: usize },
+    Puzzle { index: usize, board: Box<Board> },
+    Practice { board: Box<Board> },
+}
+
+/// Placements kept for undo in practice.
+const PRACTICE_HISTORY: usize = 200;
+
+struct PopEffect {
+    opponent: bool,
+    color: PuyoType,
+    positions: Vec<(usize, usize)>,
+    started: f32,
+}
+
 struct State {
     board: Board,
     other_board: Board,
+    /// The mirror came from a snapshot taken with a piece in hand, already dealt from its queue.
+    opponent_piece_dealt: bool,
+    /// Handicap and palette of this player and of the opponent, to check snapshots against.
+    settings: [PlayerSettings; 2],
     my_player_id: Option<u8>,
     initial_seed: u64,
+    ruleset: Ruleset,
     ws_sender: WsSender,
     ws_receiver: WsReceiver,
     
@@ -17,56 +49,55 @@ struct State {
     game_over_sent: bool,
     did_i_win: bool,
 
-    last_fall_time: f32,
-    last_resolve_time: f32,
-    played_time: f32, 
-    
-    key_timer_left: f32,
-    key_timer_right: f32,
-    key_timer_down: f32,
+    frame_accumulator: f32,
+    pop_effects: Vec<PopEffect>,
+
+    screen: Screen,
+    puzzles: Vec<Puzzle>,
 
     last_server_msg: String,
     font: Font, 
 }
 impl AppState for State {}
 
-fn setup(app: &mut App, gfx: &mut Graphics) -> State {
+fn setup(_app: &mut App, gfx: &mut Graphics) -> State {
     let (ws_sender, ws_receiver) = ewebsock::connect("ws://ADRESSE_IP:8080/ws").unwrap();
     let font = gfx.create_font(include_bytes!("arcadeFont.ttf")).unwrap();
 
-    let mut board = Board::new(GRID_WIDTH, GRID_HEIGHT, 12345);
+    let ruleset = Ruleset::tsu();
+    let settings = PlayerSettings::new(&ruleset, Handicap::default(), 12345);
+    let mut board = settings.board(&ruleset, 12345);
     board.spawn_piece();
-    let other_board = Board::new(GRID_WIDTH, GRID_HEIGHT, 12345);
-    let now = app.timer.elapsed_f32();
+    let other_board = settings.board(&ruleset, 12345);
+    let puzzles = PUZZLE_FILES.iter().filter_map(|json| serde_json::from_str(json).map_err(|e| println!("Puzzle invalide: {}", e)).ok()).collect();
 
     State {
-        board, other_board, my_player_id: None, initial_seed: 12345,
+        board, other_board, opponent_piece_dealt: false, settings: [settings.clone(), settings], my_player_id: None, initial_seed: 12345, ruleset,
         ws_sender, ws_receiver,
         waiting_for_opponent: true,
         opponent_disconnected: false,
         game_over_sent: false, did_i_win: false,
-        last_fall_time: now, last_resolve_time: now, played_time: 0.0,
-        key_timer_left: 0.0, key_timer_right: 0.0, key_timer_down: 0.0,
+        frame_accumulator: 0.0, pop_effects: Vec::new(),
+        screen: Screen::Versus, puzzles,
         last_server_msg: String::from("Connexion..."), font,
     }
 }
 
-fn update_opponent_board(board: &mut Board, col: i32, rot: usize, c1: u8, c2: u8) {
-    let piece = ActivePuyo { row: 1, col, rotation: rot, axis_type: PuyoType::from_u8(c1), sat_type: PuyoType::from_u8(c2) };
-    let mut ghost = piece.clone();
-    if board.check_collision(&ghost) { return; }
-    while !board.check_collision(&ghost) { ghost.row += 1; }
-    ghost.row -= 1; 
-    for (r, c) in ghost.get_positions().iter() {
-        if *r >= 0 && *r < board.height as i32 && *c >= 0 && *c < board.width as i32 {
-            let p_type = if *r == ghost.row && *c == ghost.col { ghost.axis_type } else { ghost.sat_type };
-            board.cells[*r as usize][*c as usize] = Some(p_type);
-        }
-    }
-    board.apply_board_gravity();
-    loop {
-        if board.check_matches() { board.apply_board_gravity(); } else { break; }
-    }
+/// Builds both boards from the per-player settings, missing ones falling back to no handicap.
+fn reset_boards(state: &mut State, players: &[PlayerSettings], seed: u64) {
+    let mine = state.my_player_id.map_or(0, |id| id.saturating_sub(1) as usize);
+    let settings = |i: usize| players.get(i).cloned().unwrap_or_else(|| PlayerSettings::new(&state.ruleset, Handicap::default(), seed));
+    let (my_settings, other_settings) = (settings(mine), settings(1 - mine.min(1)));
+    state.board = my_settings.board(&state.ruleset, seed);
+    state.board.spawn_piece();
+    state.other_board = other_settings.board(&state.ruleset, seed);
+    state.opponent_piece_dealt = false;
+    state.settings = [my_settings, other_settings];
+}
+
+fn update_opponent_board(board: &mut Board, col: i32, rot: usize, c1: u8, c2: u8, shape: PieceShape) {
+    let piece = ActivePuyo { row: board.visible_top() as i32, col, rotation: rot, axis_type: PuyoType::from_u8(c1), sat_type: PuyoType::from_u8(c2), shape };
+    if board.apply_placement(&piece).is_some() { board.drop_garbage(); }
 }
 
 fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
@@ -79,13 +110,12 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
                 WsMessage::Text(text) => {
                     if let Ok(server_msg) = serde_json::from_str::<ServerMessage>(&text) {
                        match server_msg {
-                           ServerMessage::Welcome { random_seed, player_id } => {
+                           ServerMessage::Welcome { random_seed, player_id, ruleset, players } => {
                                 state.my_player_id = Some(player_id);
                                 state.initial_seed = random_seed;
-                                state.board = Board::new(GRID_WIDTH, GRID_HEIGHT, random_seed);
-                                state.board.spawn_piece();
-                                state.other_board = Board::new(GRID_WIDTH, GRID_HEIGHT, random_seed);
-                                state.played_time = 0.0;
+                                state.ruleset = *ruleset;
+                                reset_boards(state, &players, random_seed);
+                                state.frame_accumulator = 0.0;
                                 state.game_over_sent = false;
                                 state.did_i_win = false;
                                 state.opponent_disconnected = false;
@@ -93,11 +123,48 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
                            ServerMessage::GameStart => {
                                state.waiting_for_opponent = false;
                                state.opponent_disconnected = false;
-                               state.played_time = 0.0;
+                               state.frame_accumulator = 0.0;
                            }
-                           ServerMessage::OpponentAction { player_id, col, rot, axis_color_idx, sat_color_idx } => {
+                           ServerMessage::OpponentAction { player_id, col, rot, axis_color_idx, sat_color_idx, shape, checksum, fever_timer, frame } => {
                                 if Some(player_id) != state.my_player_id {
-                                    update_opponent_board(&mut state.other_board, col, rot, axis_color_idx, sat_color_idx);
+                                    if !std::mem::take(&mut state.opponent_piece_dealt) { state.other_board.next_piece(); }
+                                    if let Some(checksum) = checksum {
+                                        let mirror = state.other_board.checksum();
+                                        if mirror != checksum { println!("Miroir de J{} désynchronisé à la pièce {}", player_id, checksum.pieces); }
+                                        let msg = ClientMessage::MirrorChecksum { player_id, checksum: mirror, field: state.other_board.to_notation() };
+                                        if let Ok(json) = serde_json::to_string(&msg) { state.ws_sender.send(WsMessage::Text(json)); }
+                                    }
+                                    if state.other_board.in_fever() { state.other_board.fever_timer = fever_timer; }
+                                    state.other_board.set_frame(frame);
+                                    update_opponent_board(&mut state.other_board, col, rot, axis_color_idx, sat_color_idx, shape);
+                                }
+                           }
+                           ServerMessage::FeverEnded { player_id } => {
+                                if Some(player_id) != state.my_player_id { state.other_board.end_fever(); }
+                           }
+                           ServerMessage::RequestBoard { player_id } => {
+                                if Some(player_id) == state.my_player_id {
+                                    let msg = ClientMessage::OwnBoard { board: Box::new(state.board.clone()) };
+                                    if let Ok(json) = serde_json::to_string(&msg) { state.ws_sender.send(WsMessage::Text(json)); }
+                                }
+                           }
+                           ServerMessage::MirrorSync { player_id, mut board } => {
+                                if Some(player_id) != state.my_player_id {
+                                    match state.settings[1].repair(&state.ruleset, &mut board) {
+                                        Ok(_) => {
+                                            while matches!(board.state, GameState::ResolvingMatches | GameState::FeverResolving) { board.resolve_step(); }
+                                            state.opponent_piece_dealt = board.active_piece.take().is_some();
+                                            state.other_board = *board;
+                                        }
+                                        Err(e) => println!("Resynchronisation ignorée: {}", e),
+                                    }
+                                }
+                           }
+                           ServerMessage::GarbageSent { player_id, amount } => {
+                                if Some(player_id) == state.my_player_id {
+                                    state.other_board.queue_garbage(amount);
+                                } else {
+                                    state.board.queue_garbage(amount);
                                 }
                            }
                            ServerMessage::PlayerEliminated { player_id } => {
@@ -106,13 +173,10 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
                                    state.board.state = GameState::GameOver; 
                                }
                            }
-                           ServerMessage::Restart { new_seed } => {
+                           ServerMessage::Restart { new_seed, players } => {
                                 state.initial_seed = new_seed;
-                                state.board = Board::new(GRID_WIDTH, GRID_HEIGHT, new_seed);
-                                state.board.spawn_piece();
-                                state.other_board = Board::new(GRID_WIDTH, GRID_HEIGHT, new_seed);
-                                state.played_time = 0.0;
-                                state.last_fall_time = app.timer.elapsed_f32();
+                                reset_boards(state, &players, new_seed);
+                                state.frame_accumulator = 0.0;
                                 state.game_over_sent = false;
                                 state.did_i_win = false;
                                 if state.board.state == GameState::Paused { state.board.state = GameState::Playing; }
@@ -122,7 +186,7 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
                            }
                            ServerMessage::OpponentDisconnected => {
                                state.opponent_disconnected = true;
-                               if state.board.state == GameState::Playing {
+                               if state.board.state.is_playing() {
                                    state.board.toggle_pause();
                                }
                            }
@@ -130,8 +194,8 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
                            ServerMessage::RequestSnapshot { requester_id } => {
                                println!("Envoi snapshot...");
                                let msg = ClientMessage::FullGameState {
-                                   my_board: state.board.clone(),
-                                   opponent_board: state.other_board.clone(),
+                                   my_board: Box::new(state.board.clone()),
+                                   opponent_board: Box::new(state.other_board.clone()),
                                    scores: (state.board.score, state.other_board.score),
                                    requester_id
                                };
@@ -141,25 +205,28 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
                                state.opponent_disconnected = false;
                            }
                            
-                           ServerMessage::SyncState { my_board, opponent_board, scores, target_player_id } => {
+                           ServerMessage::SyncState { mut my_board, mut opponent_board, scores, target_player_id } => {
                                if Some(target_player_id) == state.my_player_id {
+                                   if let Err(e) = state.settings[0].repair(&state.ruleset, &mut my_board).and_then(|_| state.settings[1].repair(&state.ruleset, &mut opponent_board)) {
+                                       println!("Snapshot invalide ignoré: {}", e);
+                                       continue;
+                                   }
                                    println!("📦 REÇU SNAPSHOT !");
-                                   state.board = my_board;
-                                   state.other_board = opponent_board;
+                                   state.board = *my_board;
+                                   state.other_board = *opponent_board;
+                                   state.opponent_piece_dealt = state.other_board.active_piece.take().is_some();
                                    state.board.score = scores.0;
                                    state.other_board.score = scores.1;
 
-                                   if state.board.active_piece.is_none() && state.board.state == GameState::Playing {
+                                   if state.board.active_piece.is_none() && state.board.state.is_playing() {
                                        state.board.spawn_piece();
                                    }
-                                   let now = app.timer.elapsed_f32();
-                                   state.last_fall_time = now;
-                                   state.last_resolve_time = now;
+                                   state.frame_accumulator = 0.0;
                                    state.waiting_for_opponent = false; 
                                    state.opponent_disconnected = false;
                                    if state.board.state == GameState::Paused {
                                    } else {
-                                        state.board.state = GameState::Playing;
+                                        state.board.state = if state.board.in_fever() { GameState::Fever } else { GameState::Playing };
                                    }
                                } else {
                                    println!("Adversaire synchro.");
@@ -182,20 +249,21 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
         }
     }
 
-    let time_now = app.timer.elapsed_f32();
-    let delta_time = app.timer.delta_f32();
+    if !matches!(state.screen, Screen::Versus) {
+        update_offline_screen(app, &mut draw, state);
+        gfx.render(&draw);
+        return;
+    }
+    if app.keyboard.was_pressed(KeyCode::P) && !state.puzzles.is_empty() && (state.waiting_for_opponent || state.board.state == GameState::GameOver) {
+        state.screen = Screen::PuzzleSelect { selected: 0 };
+    }
+    if app.keyboard.was_pressed(KeyCode::T) && (state.waiting_for_opponent || state.board.state == GameState::GameOver) {
+        state.screen = new_practice(&state.ruleset);
+    }
 
     let can_play = !state.waiting_for_opponent && !state.opponent_disconnected;
 
     if can_play {
-        if state.board.state == GameState::Playing || state.board.state == GameState::ResolvingMatches {
-            state.played_time += delta_time;
-        }
-
-        let level = 1 + (state.played_time / 15.0) as u32;
-        let speed_decrease = (level as f64 - 1.0) * 0.05;
-        let current_interval = if speed_decrease >= (0.8 - 0.1) { 0.1 } else { 0.8 - speed_decrease };
-
         if app.keyboard.was_pressed(KeyCode::R) && (state.board.state == GameState::GameOver || state.board.state == GameState::Paused) {
             let msg = ClientMessage::RequestRestart;
             if let Ok(json) = serde_json::to_string(&msg) { state.ws_sender.send(WsMessage::Text(json)); }
@@ -212,79 +280,45 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
             state.game_over_sent = true;
         }
 
-        if state.board.state == GameState::Playing {
-            if app.keyboard.was_pressed(KeyCode::Up) || app.keyboard.was_pressed(KeyCode::Z) { state.board.rotate_piece(1); }
-            if app.keyboard.was_pressed(KeyCode::X) || app.keyboard.was_pressed(KeyCode::W) { state.board.rotate_piece(3); }
-
-            let piece_locked_now = if app.keyboard.was_pressed(KeyCode::Space) || app.keyboard.was_pressed(KeyCode::Return) {
-                if let Some(piece) = &state.board.active_piece {
-                    let action_msg = ClientMessage::PieceLocked { 
-                        col: piece.col, rot: piece.rotation, 
-                        axis_color_idx: piece.axis_type.to_u8(), sat_color_idx: piece.sat_type.to_u8() 
-                    };
-                    if let Ok(json) = serde_json::to_string(&action_msg) { state.ws_sender.send(WsMessage::Text(json)); }
-                }
-                state.board.hard_drop();
-                state.last_fall_time = time_now;
-                true
-            } else { false };
-
-            if !piece_locked_now {
-                if app.keyboard.is_down(KeyCode::Left) {
-                    if state.key_timer_left == 0.0 { state.board.move_piece(-1); state.key_timer_left = 0.0001; }
-                    else {
-                        state.key_timer_left += delta_time;
-                        if state.key_timer_left > DAS_DELAY { while state.key_timer_left > DAS_DELAY + DAS_SPEED { state.board.move_piece(-1); state.key_timer_left -= DAS_SPEED; } }
-                    }
-                } else { state.key_timer_left = 0.0; }
+        let mut input = read_input(app);
 
-                if app.keyboard.is_down(KeyCode::Right) {
-                    if state.key_timer_right == 0.0 { state.board.move_piece(1); state.key_timer_right = 0.0001; }
-                    else {
-                        state.key_timer_right += delta_time;
-                        if state.key_timer_right > DAS_DELAY { while state.key_timer_right > DAS_DELAY + DAS_SPEED { state.board.move_piece(1); state.key_timer_right -= DAS_SPEED; } }
-                    }
-                } else { state.key_timer_right = 0.0; }
-
-                if app.keyboard.is_down(KeyCode::Down) {
-                    state.key_timer_down += delta_time;
-                    if state.key_timer_down > SOFT_DROP_SPEED {
-                        state.board.force_drop();
-                        state.last_fall_time = time_now;
-                        state.key_timer_down = 0.0;
-                    }
-                } else { state.key_timer_down = 0.0; }
-            }
+        state.frame_accumulator = (state.frame_accumulator + app.timer.delta_f32()).min(FRAME_TIME * MAX_FRAMES_PER_DRAW as f32);
+        while state.frame_accumulator >= FRAME_TIME {
+            state.frame_accumulator -= FRAME_TIME;
+            state.board.tick(input);
+            // Only counts down for display, the mirror's fever ends on the opponent's FeverEnded.
+            if state.other_board.in_fever() { state.other_board.fever_timer = state.other_board.fever_timer.saturating_sub(1); }
+            input = InputFrame { hard_drop: false, rotate_cw: false, rotate_ccw: false, ..input };
         }
+    }
 
-        match state.board.state {
-            GameState::Playing => {
-                let pending_lock_msg = if let Some(piece) = &state.board.active_piece {
-                    Some(ClientMessage::PieceLocked { 
-                        col: piece.col, rot: piece.rotation, axis_color_idx: piece.axis_type.to_u8(), sat_color_idx: piece.sat_type.to_u8() 
-                    })
-                } else { None };
-
-                let locked = state.board.update_logic(delta_time);
-                if locked {
-                    if let Some(msg) = pending_lock_msg { if let Ok(json) = serde_json::to_string(&msg) { state.ws_sender.send(WsMessage::Text(json)); } }
-                    state.last_fall_time = time_now;
-                } else {
-                    if !state.board.is_touching_ground && (time_now - state.last_fall_time > current_interval as f32) {
-                        state.board.force_drop();
-                        state.last_fall_time = time_now;
-                    }
-                }
-            },
-            GameState::ResolvingMatches => {
-                if time_now - state.last_resolve_time > 0.15 { state.board.resolve_step(); state.last_resolve_time = time_now; }
-            },
-            _ => {}
+    let now = app.timer.elapsed_f32();
+    for event in state.board.drain_events() {
+        let msg = match event {
+            BoardEvent::PieceLocked { piece, checksum, fever_timer, frame } => Some(ClientMessage::PieceLocked {
+                col: piece.col, rot: piece.rotation, axis_color_idx: piece.axis_type.to_u8(), sat_color_idx: piece.sat_type.to_u8(), shape: piece.shape, checksum, fever_timer, frame
+            }),
+            BoardEvent::FeverEnded => Some(ClientMessage::FeverEnded),
+            BoardEvent::ChainStep { garbage, .. } if garbage > 0 => Some(ClientMessage::SendGarbage { amount: garbage }),
+            BoardEvent::GroupsPopped { groups, specials_cleared, .. } => {
+                state.pop_effects.extend(groups.into_iter().chain(specials_cleared).map(|g| PopEffect { opponent: false, color: g.color, positions: g.positions, started: now }));
+                None
+            }
+            _ => None,
+        };
+        if let Some(msg) = msg {
+            if let Ok(json) = serde_json::to_string(&msg) { state.ws_sender.send(WsMessage::Text(json)); }
+        }
+    }
+    for event in state.other_board.drain_events() {
+        if let BoardEvent::GroupsPopped { groups, specials_cleared, .. } = event {
+            state.pop_effects.extend(groups.into_iter().chain(specials_cleared).map(|g| PopEffect { opponent: true, color: g.color, positions: g.positions, started: now }));
         }
     }
+    state.pop_effects.retain(|e| now - e.started < POP_EFFECT_TIME);
 
-    let board_w = GRID_WIDTH as f32 * CELL_SIZE;
-    let board_h = (GRID_HEIGHT - VISIBLE_ROW_OFFSET) as f32 * CELL_SIZE;
+    let board_w = state.board.width as f32 * CELL_SIZE;
+    let board_h = state.board.visible_rows() as f32 * CELL_SIZE;
     let gap = 250.0;
     let total_w = board_w * 2.0 + gap; 
     let start_x = (app.window().width() as f32 - total_w) / 2.0;
@@ -297,30 +331,52 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
     let opponent_x = start_x + board_w + gap;
     draw_board(&mut draw, &state.other_board, opponent_x, offset_y, board_w, board_h);
     draw.text(&state.font, "OPPONENT").position(opponent_x, offset_y - 30.0).size(20.0).color(Color::GRAY);
+    draw_pending_garbage(&mut draw, &state.font, &state.board, start_x + board_w, offset_y);
+    draw_pending_garbage(&mut draw, &state.font, &state.other_board, opponent_x + board_w, offset_y);
+    for effect in state.pop_effects.iter() {
+        let (x, top) = if effect.opponent { (opponent_x, state.other_board.visible_top()) } else { (start_x, state.board.visible_top()) };
+        let alpha = 1.0 - (now - effect.started) / POP_EFFECT_TIME;
+        for (r, c) in effect.positions.iter() {
+            if *r < top { continue; }
+            let (cx, cy) = (x + (*c as f32 + 0.5) * CELL_SIZE, offset_y + ((*r - top) as f32 + 0.5) * CELL_SIZE);
+            let mut color = get_puyo_color(effect.color);
+            color.a = alpha;
+            draw.circle(CELL_SIZE * (0.5 + (1.0 - alpha) * 0.4)).position(cx, cy).color(color);
+        }
+    }
+    draw_all_clear(&mut draw, &state.font, &state.board, start_x, offset_y, board_w, board_h);
+    draw_all_clear(&mut draw, &state.font, &state.other_board, opponent_x, offset_y, board_w, board_h);
+    draw_fever(&mut draw, &state.font, &state.board, start_x, offset_y, board_w, board_h);
+    draw_fever(&mut draw, &state.font, &state.other_board, opponent_x, offset_y, board_w, board_h);
 
     draw.text(&state.font, &format!("Score: {}", state.board.score)).position(ui_x, offset_y + 20.0).size(30.0).color(Color::WHITE);
-    draw.text(&state.font, &format!("Level: {}", 1 + (state.played_time / 15.0) as u32)).position(ui_x, offset_y + 60.0).size(30.0).color(Color::YELLOW);
+    draw.text(&state.font, &format!("Level: {}", state.board.level())).position(ui_x, offset_y + 60.0).size(30.0).color(Color::YELLOW);
+    let target_color = if state.board.target_point < state.board.ruleset.target_point { Color::RED } else { Color::GRAY };
+    draw.text(&state.font, &format!("Target: {}", state.board.target_point)).position(ui_x + 160.0, offset_y + 66.0).size(20.0).color(target_color);
 
     draw.text(&state.font, "Next:").position(ui_x, offset_y + 110.0).size(30.0).color(Color::GRAY);
-    draw.rect((ui_x, offset_y + 140.0), (CELL_SIZE, CELL_SIZE * 2.1)).color(Color::from_rgb(0.2, 0.2, 0.2));
-    draw_cell(&mut draw, 0.0, 0.0, Some(state.board.next_types.1), ui_x, offset_y + 140.0, 1.0);
-    draw_cell(&mut draw, 1.0, 0.0, Some(state.board.next_types.0), ui_x, offset_y + 140.0, 1.0);
-
     let next_next_y = offset_y + 170.0 + (CELL_SIZE * 2.5);
-    draw.text(&state.font, "Next Next:").position(ui_x, next_next_y - 25.0).size(20.0).color(Color::GRAY);
-    draw.rect((ui_x, next_next_y), (CELL_SIZE, CELL_SIZE * 2.1)).color(Color::from_rgb(0.15, 0.15, 0.15));
-    draw_cell(&mut draw, 0.0, 0.0, Some(state.board.next_next_types.1), ui_x, next_next_y, 1.0);
-    draw_cell(&mut draw, 1.0, 0.0, Some(state.board.next_next_types.0), ui_x, next_next_y, 1.0);
+    for (i, pair) in state.board.preview.iter().enumerate() {
+        let (x, y, shade) = if i == 0 { (ui_x, offset_y + 140.0, 0.2) } else { (ui_x + (i - 1) as f32 * (CELL_SIZE + 10.0), next_next_y, 0.15) };
+        if i == 1 { draw.text(&state.font, "Next Next:").position(ui_x, next_next_y - 25.0).size(20.0).color(Color::GRAY); }
+        draw.rect((x, y), (CELL_SIZE, CELL_SIZE * 2.1)).color(Color::from_rgb(shade, shade, shade));
+        draw_cell(&mut draw, 0.0, 0.0, Some(pair.1), x, y, 1.0);
+        draw_cell(&mut draw, 1.0, 0.0, Some(pair.0), x, y, 1.0);
+    }
 
     if state.board.chain_count > 0 {
         draw.text(&state.font, &format!("Chain: {}", state.board.chain_count)).position(ui_x, offset_y + 380.0).size(30.0).color(Color::GREEN);
     }
+    if state.board.chain_steps.is_empty() {
+        if let Some(steps) = state.board.chain_history.back() { draw_chain_breakdown(&mut draw, &state.font, steps, ui_x, offset_y + 420.0); }
+    }
 
-    if state.board.is_touching_ground && state.board.state == GameState::Playing {
-        let ratio_std = 1.0 - (state.board.lock_timer / MAX_LOCK_TIME);
-        let ratio_hard = 1.0 - (state.board.total_ground_timer / MAX_TOTAL_GROUND_TIME);
+    if state.board.is_touching_ground && state.board.state.is_playing() {
+        let rules = &state.board.ruleset;
+        let ratio_std = 1.0 - (state.board.lock_timer as f32 / rules.lock_frames as f32);
+        let ratio_hard = 1.0 - (state.board.total_ground_timer as f32 / rules.max_ground_frames as f32);
         let ratio = ratio_std.min(ratio_hard).max(0.0);
-        let col = if state.board.total_ground_timer > 1.5 { Color::RED } else { Color::ORANGE };
+        let col = if state.board.total_ground_timer * 4 > rules.max_ground_frames * 3 { Color::RED } else { Color::ORANGE };
         draw.rect((ui_x, offset_y + 350.0), (100.0 * ratio, 10.0)).color(col);
     }
 
@@ -330,6 +386,7 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
     if state.waiting_for_opponent {
         draw.rect((0.0, 0.0), (win_w, win_h)).color(Color::from_rgba(0.0, 0.0, 0.0, 0.8));
         draw.text(&state.font, "WAITING FOR PLAYER 2...").position(win_w / 2.0, win_h / 2.0).size(40.0).h_align_center().v_align_middle().color(Color::WHITE);
+        draw.text(&state.font, "Press P for puzzles, T for practice").position(win_w / 2.0, win_h / 2.0 + 50.0).size(20.0).h_align_center().v_align_middle().color(Color::GRAY);
     }
 
     if state.opponent_disconnected {
@@ -359,44 +416,218 @@ fn draw(app: &mut App, gfx: &mut Graphics, state: &mut State) {
     gfx.render(&draw);
 }
 
+fn read_input(app: &App) -> InputFrame {
+    InputFrame {
+        left: app.keyboard.is_down(KeyCode::Left),
+        right: app.keyboard.is_down(KeyCode::Right),
+        soft_drop: app.keyboard.is_down(KeyCode::Down),
+        hard_drop: app.keyboard.was_pressed(KeyCode::Space) || app.keyboard.was_pressed(KeyCode::Return),
+        rotate_cw: app.keyboard.was_pressed(KeyCode::Up) || app.keyboard.was_pressed(KeyCode::Z),
+        rotate_ccw: app.keyboard.was_pressed(KeyCode::X) || app.keyboard.was_pressed(KeyCode::W),
+    }
+}
+
+fn load_puzzle(puzzles: &[Puzzle], index: usize) -> Option<Screen> {
+    match puzzles[index].board() {
+        Ok(mut board) => { board.spawn_piece(); Some(Screen::Puzzle { index, board: Box::new(board) }) }
+        Err(e) => { println!("Puzzle '{}' invalide: {}", puzzles[index].name, e); None }
+    }
+}
+
+/// Offline board with a fresh seed and undo history, rules taken from the last match.
+fn new_practice(ruleset: &Ruleset) -> Screen {
+    let seed: u64 = rand::random();
+    let mut board = Board::new(ruleset.clone(), PuyoType::palette(ruleset.color_count, seed), seed);
+    board.spawn_piece();
+    board.enable_history(PRACTICE_HISTORY);
+    Screen::Practice { board: Box::new(board) }
+}
+
+fn run_frames(app: &App, frame_accumulator: &mut f32, board: &mut Board) {
+    let mut input = read_input(app);
+    *frame_accumulator = (*frame_accumulator + app.timer.delta_f32()).min(FRAME_TIME * MAX_FRAMES_PER_DRAW as f32);
+    while *frame_accumulator >= FRAME_TIME {
+        *frame_accumulator -= FRAME_TIME;
+        board.tick(input);
+        input = InputFrame { hard_drop: false, rotate_cw: false, rotate_ccw: false, ..input };
+    }
+    board.drain_events();
+}
+
+fn update_offline_screen(app: &mut App, draw: &mut Draw, state: &mut State) {
+    let win_w = app.window().width() as f32;
+    let win_h = app.window().height() as f32;
+    let next = match &mut state.screen {
+        Screen::Versus => None,
+        Screen::PuzzleSelect { selected } => {
+            let count = state.puzzles.len();
+            if app.keyboard.was_pressed(KeyCode::Up) { *selected = (*selected + count - 1) % count; }
+            if app.keyboard.was_pressed(KeyCode::Down) { *selected = (*selected + 1) % count; }
+            draw.text(&state.font, "PUZZLES").position(win_w / 2.0, 80.0).size(50.0).h_align_center().color(Color::YELLOW);
+            for (i, puzzle) in state.puzzles.iter().enumerate() {
+                let color = if i == *selected { Color::WHITE } else { Color::GRAY };
+                let line = format!("{}. {} - {}", i + 1, puzzle.name, puzzle.objective.describe());
+                draw.text(&state.font, &line).position(win_w / 2.0, 180.0 + i as f32 * 45.0).size(25.0).h_align_center().color(color);
+            }
+            draw.text(&state.font, "Enter: play   Esc: back").position(win_w / 2.0, win_h - 60.0).size(20.0).h_align_center().color(Color::GRAY);
+            if app.keyboard.was_pressed(KeyCode::Escape) { Some(Screen::Versus) }
+            else if app.keyboard.was_pressed(KeyCode::Return) { load_puzzle(&state.puzzles, *selected) }
+            else { None }
+        }
+        Screen::Puzzle { index, board } => {
+            run_frames(app, &mut state.frame_accumulator, board);
+
+            let board_w = board.width as f32 * CELL_SIZE;
+            let board_h = board.visible_rows() as f32 * CELL_SIZE;
+            let (offset_x, offset_y) = ((win_w - board_w) / 2.0 - 100.0, (win_h - board_h) / 2.0);
+            let ui_x = offset_x + board_w + 30.0;
+            draw_board(draw, board, offset_x, offset_y, board_w, board_h);
+            draw.text(&state.font, &state.puzzles[*index].name).position(offset_x, offset_y - 30.0).size(20.0).color(Color::WHITE);
+            if let Some(progress) = board.puzzle.as_ref() {
+                draw.text(&state.font, &progress.objective.describe()).position(ui_x, offset_y + 20.0).size(25.0).color(Color::YELLOW);
+            }
+            let remaining = board.preview.len() + if let PairQueue::Scripted(queue) = &board.queue { queue.remaining() } else { 0 };
+            draw.text(&state.font, &format!("Pairs left: {}", remaining)).position(ui_x, offset_y + 60.0).size(25.0).color(Color::GRAY);
+            for (i, pair) in board.preview.iter().enumerate() {
+                let x = ui_x + i as f32 * (CELL_SIZE + 10.0);
+                draw_cell(draw, 0.0, 0.0, Some(pair.1), x, offset_y + 100.0, 1.0);
+                draw_cell(draw, 1.0, 0.0, Some(pair.0), x, offset_y + 100.0, 1.0);
+            }
+            draw.text(&state.font, "R: retry   Esc: puzzles").position(ui_x, offset_y + board_h - 20.0).size(20.0).color(Color::GRAY);
+            if let Some(solved) = board.puzzle.as_ref().and_then(|p| p.solved) {
+                let (text, color) = if solved { ("SOLVED!", Color::YELLOW) } else { ("FAILED", Color::RED) };
+                draw.rect((offset_x, offset_y + board_h / 2.0 - 40.0), (board_w, 80.0)).color(Color::from_rgba(0.0, 0.0, 0.0, 0.7));
+                draw.text(&state.font, text).position(offset_x + board_w / 2.0, offset_y + board_h / 2.0).size(50.0).h_align_center().v_align_middle().color(color);
+            }
+            if app.keyboard.was_pressed(KeyCode::Escape) { Some(Screen::PuzzleSelect { selected: *index }) }
+            else if app.keyboard.was_pressed(KeyCode::R) { load_puzzle(&state.puzzles, *index) }
+            else { None }
+        }
+        Screen::Practice { board } => {
+            if app.keyboard.was_pressed(KeyCode::U) { board.undo(); }
+            if app.keyboard.was_pressed(KeyCode::Y) { board.redo(); }
+            run_frames(app, &mut state.frame_accumulator, board);
+
+            let board_w = board.width as f32 * CELL_SIZE;
+            let board_h = board.visible_rows() as f32 * CELL_SIZE;
+            let (offset_x, offset_y) = ((win_w - board_w) / 2.0 - 100.0, (win_h - board_h) / 2.0);
+            let ui_x = offset_x + board_w + 30.0;
+            draw_board(draw, board, offset_x, offset_y, board_w, board_h);
+            draw_all_clear(draw, &state.font, board, offset_x, offset_y, board_w, board_h);
+            draw_fever(draw, &state.font, board, offset_x, offset_y, board_w, board_h);
+            draw.text(&state.font, "PRACTICE").position(offset_x, offset_y - 30.0).size(20.0).color(Color::WHITE);
+            draw.text(&state.font, &format!("Score: {}", board.score)).position(ui_x, offset_y + 20.0).size(30.0).color(Color::WHITE);
+            draw.text(&state.font, &format!("Pieces: {}", board.piece_count)).position(ui_x, offset_y + 60.0).size(25.0).color(Color::GRAY);
+            for (i, pair) in board.preview.iter().enumerate() {
+                let x = ui_x + i as f32 * (CELL_SIZE + 10.0);
+                draw_cell(draw, 0.0, 0.0, Some(pair.1), x, offset_y + 100.0, 1.0);
+                draw_cell(draw, 1.0, 0.0, Some(pair.0), x, offset_y + 100.0, 1.0);
+            }
+            if board.chain_count > 0 {
+                draw.text(&state.font, &format!("Chain: {}", board.chain_count)).position(ui_x, offset_y + 200.0).size(30.0).color(Color::GREEN);
+            }
+            if board.chain_steps.is_empty() {
+                if let Some(steps) = board.chain_history.back() { draw_chain_breakdown(draw, &state.font, steps, ui_x, offset_y + 240.0); }
+            }
+            let undo_color = |enabled: bool| if enabled { Color::WHITE } else { Color::GRAY };
+            draw.text(&state.font, "U: undo").position(ui_x, offset_y + board_h - 50.0).size(20.0).color(undo_color(board.can_undo()));
+            draw.text(&state.font, "Y: redo").position(ui_x + 110.0, offset_y + board_h - 50.0).size(20.0).color(undo_color(board.can_redo()));
+            draw.text(&state.font, "R: new   Esc: back").position(ui_x, offset_y + board_h - 20.0).size(20.0).color(Color::GRAY);
+            if board.state == GameState::GameOver {
+                draw.rect((offset_x, offset_y + board_h / 2.0 - 40.0), (board_w, 80.0)).color(Color::from_rgba(0.0, 0.0, 0.0, 0.7));
+                draw.text(&state.font, "GAME OVER").position(offset_x + board_w / 2.0, offset_y + board_h / 2.0).size(40.0).h_align_center().v_align_middle().color(Color::RED);
+            }
+            if app.keyboard.was_pressed(KeyCode::Escape) { Some(Screen::Versus) }
+            else if app.keyboard.was_pressed(KeyCode::R) { Some(new_practice(&state.ruleset)) }
+            else { None }
+        }
+    };
+    if let Some(screen) = next { state.frame_accumulator = 0.0; state.screen = screen; }
+}
+
+const BREAKDOWN_MAX_STEPS: usize = 8;
+
+fn draw_chain_breakdown(draw: &mut Draw, font: &Font, steps: &[ChainStepReport], x: f32, y: f32) {
+    draw.text(font, "Last chain  (CP+CB+GB)").position(x, y).size(16.0).color(Color::GRAY);
+    let skipped = steps.len().saturating_sub(BREAKDOWN_MAX_STEPS);
+    for (i, step) in steps.iter().skip(skipped).enumerate() {
+        let line = format!("{}: {}x ({}+{}+{}={}) {} pts {} atk", step.chain, step.cleared, step.chain_power, step.color_bonus, step.group_bonus, step.multiplier, step.points, step.garbage);
+        draw.text(font, &line).position(x, y + 20.0 + i as f32 * 18.0).size(14.0).color(Color::WHITE);
+    }
+    let (points, garbage) = steps.iter().fold((0, 0), |(p, g), s| (p + s.points, g + s.garbage));
+    let total_y = y + 24.0 + (steps.len() - skipped) as f32 * 18.0;
+    draw.text(font, &format!("Total {} pts {} atk", points, garbage)).position(x, total_y).size(16.0).color(Color::YELLOW);
+}
+
+fn draw_pending_garbage(draw: &mut Draw, font: &Font, board: &Board, right_x: f32, offset_y: f32) {
+    if board.pending_garbage > 0 {
+        draw.text(font, &format!("+{}", board.pending_garbage)).position(right_x, offset_y - 30.0).size(20.0).h_align_right().color(Color::RED);
+    }
+}
+
+fn draw_all_clear(draw: &mut Draw, font: &Font, board: &Board, offset_x: f32, offset_y: f32, board_w: f32, board_h: f32) {
+    if board.all_clear {
+        draw.rect((offset_x, offset_y + board_h / 2.0 - 30.0), (board_w, 60.0)).color(Color::from_rgba(0.0, 0.0, 0.0, 0.6));
+        draw.text(font, "ALL CLEAR!").position(offset_x + board_w / 2.0, offset_y + board_h / 2.0).size(36.0).h_align_center().v_align_middle().color(Color::YELLOW);
+    }
+}
+
+fn draw_fever(draw: &mut Draw, font: &Font, board: &Board, offset_x: f32, offset_y: f32, board_w: f32, board_h: f32) {
+    let max = board.ruleset.fever_gauge;
+    if max == 0 { return; }
+    let segment_w = board_w / max as f32;
+    for i in 0..max {
+        let color = if board.in_fever() || i < board.fever_gauge { Color::ORANGE } else { Color::from_rgb(0.25, 0.25, 0.25) };
+        draw.rect((offset_x + i as f32 * segment_w + 1.0, offset_y + board_h + 8.0), (segment_w - 2.0, 10.0)).color(color);
+    }
+    if board.in_fever() {
+        let seconds = board.fever_timer as f32 / FRAME_RATE as f32;
+        draw.text(font, &format!("FEVER {:.1}", seconds)).position(offset_x, offset_y + board_h + 24.0).size(20.0).color(Color::ORANGE);
+    }
+}
+
 fn get_puyo_color(puyo_type: PuyoType) -> Color {
     match puyo_type {
         PuyoType::Red => Color::RED, PuyoType::Blue => Color::BLUE,
         PuyoType::Yellow => Color::YELLOW, PuyoType::Green => Color::GREEN,
-        PuyoType::Purple => Color::MAGENTA,
+        PuyoType::Purple => Color::MAGENTA, PuyoType::Garbage => Color::from_rgb(0.75, 0.75, 0.8),
+        PuyoType::Hard => Color::from_rgb(0.4, 0.4, 0.45), PuyoType::Point => Color::WHITE,
+        PuyoType::Sun => Color::from_rgb(1.0, 0.6, 0.1),
     }
 }
 
 fn draw_board(draw: &mut Draw, board: &Board, offset_x: f32, offset_y: f32, board_w: f32, board_h: f32) {
-    draw.rect((offset_x, offset_y), (board_w, board_h)).color(Color::from_rgb(0.12, 0.12, 0.12));
-    let x_cross = offset_x + (2.0 * CELL_SIZE) + 10.0;
-    let y_cross = offset_y + 10.0;
-    draw.line((x_cross, y_cross), (x_cross + 20.0, y_cross + 20.0)).width(3.0).color(Color::RED);
-    draw.line((x_cross + 20.0, y_cross), (x_cross, y_cross + 20.0)).width(3.0).color(Color::RED);
+    let background = if board.in_fever() { Color::from_rgb(0.22, 0.1, 0.05) } else { Color::from_rgb(0.12, 0.12, 0.12) };
+    draw.rect((offset_x, offset_y), (board_w, board_h)).color(background);
+    let top = board.visible_top();
+    for (r, c) in board.death_cells().filter(|(r, _)| *r >= top) {
+        let x_cross = offset_x + (c as f32 * CELL_SIZE) + 10.0;
+        let y_cross = offset_y + ((r - top) as f32 * CELL_SIZE) + 10.0;
+        draw.line((x_cross, y_cross), (x_cross + 20.0, y_cross + 20.0)).width(3.0).color(Color::RED);
+        draw.line((x_cross + 20.0, y_cross), (x_cross, y_cross + 20.0)).width(3.0).color(Color::RED);
+    }
 
-    for r in VISIBLE_ROW_OFFSET..board.height {
+    for r in top..board.height {
         for c in 0..board.width {
-            let draw_r = (r - VISIBLE_ROW_OFFSET) as f32;
+            let draw_r = (r - top) as f32;
             draw_cell(draw, draw_r, c as f32, board.cells[r][c], offset_x, offset_y, 1.0);
         }
     }
 
-    if board.state == GameState::Playing || board.state == GameState::Paused {
+    if board.state.is_playing() || board.state == GameState::Paused {
         if let Some(ghost) = board.get_ghost_piece() {
-            for pos in ghost.get_positions().iter() {
-                let p_type = if pos.0 == ghost.row && pos.1 == ghost.col { ghost.axis_type } else { ghost.sat_type };
-                draw_cell(draw, pos.0 as f32 - VISIBLE_ROW_OFFSET as f32, pos.1 as f32, Some(p_type), offset_x, offset_y, 0.3);
+            for ((r, c), p_type) in ghost.cells() {
+                draw_cell(draw, r as f32 - top as f32, c as f32, Some(p_type), offset_x, offset_y, 0.3);
             }
         }
         if let Some(ref piece) = board.active_piece {
-            for pos in piece.get_positions().iter() {
-                let p_type = if pos.0 == piece.row && pos.1 == piece.col { piece.axis_type } else { piece.sat_type };
-                draw_cell(draw, pos.0 as f32 - VISIBLE_ROW_OFFSET as f32, pos.1 as f32, Some(p_type), offset_x, offset_y, 1.0);
+            for ((r, c), p_type) in piece.cells() {
+                draw_cell(draw, r as f32 - top as f32, c as f32, Some(p_type), offset_x, offset_y, 1.0);
             }
         }
     }
 
-    let visible_height = (board.height - VISIBLE_ROW_OFFSET) as f32;
+    let visible_height = board.visible_rows() as f32;
     for i in 0..=board.width {
         let x = offset_x + (i as f32 * CELL_SIZE);
         draw.line((x, offset_y), (x, offset_y + board_h)).width(1.0).color(Color::GRAY);
@@ -3,11 +3,18 @@ use tokio::sync::{broadcast};
 use warp::Filter;
 use std::sync::{Arc, Mutex};
 use rand::Rng;
-use shared::{ServerMessage, ClientMessage};
+use shared::{ServerMessage, ClientMessage, Board, BoardChecksum, BoardError, Handicap, PlayerSettings, Ruleset};
+
+/// More nuisance than one chain step can send, anything above is dropped instead of relayed.
+const MAX_GARBAGE_SENT: u32 = 100_000;
 
 struct GameState {
     player_count: usize,
     seed: u64,
+    ruleset: Ruleset,
+    handicaps: Vec<Handicap>,
+    /// Last checksum each player reported of its own board.
+    checksums: [Option<BoardChecksum>; 2],
     is_running: bool, 
     is_paused: bool, 
 }
@@ -17,12 +24,25 @@ async fn main() {
     let port = 8080;
     println!("Serveur Puyo sur ws://0.0.0.0:{}", port);
 
+    let ruleset = match std::env::args().nth(1) {
+        Some(arg) => load_ruleset(&arg),
+        None => Ruleset::tsu(),
+    };
+    println!("Règles: {}", ruleset.name);
+    let handicaps = match std::env::args().nth(2) {
+        Some(path) => load_handicaps(&path),
+        None => vec![Handicap::default(); 2],
+    };
+
     let mut rng = rand::rng();
     let game_seed: u64 = rng.random();
     
     let game_state = Arc::new(Mutex::new(GameState {
         player_count: 0,
         seed: game_seed,
+        ruleset,
+        handicaps,
+        checksums: [None; 2],
         is_running: false,
         is_paused: false, 
     }));
@@ -40,6 +60,47 @@ async fn main() {
     warp::serve(ws_route).run(([0, 0, 0, 0], port)).await;
 }
 
+fn load_ruleset(arg: &str) -> Ruleset {
+    if let Some(preset) = Ruleset::preset(arg) { return preset; }
+    match std::fs::read_to_string(arg).map_err(|e| e.to_string()).and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string())) {
+        Ok(ruleset) => ruleset,
+        Err(e) => {
+            println!("Règles '{}' invalides ({}), presets: {:?}. Utilisation de tsu.", arg, e, Ruleset::PRESETS);
+            Ruleset::tsu()
+        }
+    }
+}
+
+/// Handicaps of players 1 and 2 from a JSON array, missing entries and fields left at their defaults.
+fn load_handicaps(path: &str) -> Vec<Handicap> {
+    let mut handicaps = match std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|json| serde_json::from_str::<Vec<Handicap>>(&json).map_err(|e| e.to_string())) {
+        Ok(handicaps) => handicaps,
+        Err(e) => {
+            println!("Handicaps '{}' invalides ({}). Aucun handicap.", path, e);
+            Vec::new()
+        }
+    };
+    handicaps.resize(2, Handicap::default());
+    for (i, handicap) in handicaps.iter().enumerate() { println!("Handicap J{}: {:?}", i + 1, handicap); }
+    handicaps
+}
+
+fn player_settings(gs: &GameState, seed: u64) -> Vec<PlayerSettings> {
+    gs.handicaps.iter().map(|handicap| PlayerSettings::new(&gs.ruleset, handicap.clone(), seed)).collect()
+}
+
+/// Checks a board sent by a client against the ruleset and palette of `player_id` in the current
+/// game, repairing what can be repaired and logging each fix.
+fn check_snapshot(board: &mut Board, state: &Mutex<GameState>, player_id: u8) -> Result<(), BoardError> {
+    let (ruleset, settings) = {
+        let gs = state.lock().unwrap();
+        let Some(handicap) = gs.handicaps.get((player_id as usize).wrapping_sub(1)) else { return Err(BoardError::WrongRuleset) };
+        (gs.ruleset.clone(), PlayerSettings::new(&gs.ruleset, handicap.clone(), gs.seed))
+    };
+    for fixed in settings.repair(&ruleset, board)? { println!("Snapshot réparé: {}", fixed); }
+    Ok(())
+}
+
 async fn handle_connection(
     ws: warp::ws::WebSocket, 
     tx: broadcast::Sender<String>, 
@@ -50,6 +111,8 @@ async fn handle_connection(
 
     let my_id;
     let seed;
+    let ruleset;
+    let players;
     let should_start_game;
     let is_reconnecting;
 
@@ -58,6 +121,8 @@ async fn handle_connection(
         gs.player_count += 1;
         my_id = gs.player_count as u8;
         seed = gs.seed;
+        ruleset = Box::new(gs.ruleset.clone());
+        players = player_settings(&gs, seed);
         
         is_reconnecting = gs.is_running && gs.player_count == 2;
         should_start_game = !gs.is_running && gs.player_count == 2;
@@ -65,11 +130,12 @@ async fn handle_connection(
         if should_start_game {
             gs.is_running = true;
             gs.is_paused = false; 
+            gs.checksums = [None; 2];
         }
         println!("J{} connecté. Total: {} (Reco: {})", my_id, gs.player_count, is_reconnecting);
     }
 
-    let welcome_msg = ServerMessage::Welcome { player_id: my_id, random_seed: seed };
+    let welcome_msg = ServerMessage::Welcome { player_id: my_id, random_seed: seed, ruleset, players };
     if let Ok(json) = serde_json::to_string(&welcome_msg) {
         let _ = user_ws_tx.send(warp::ws::Message::text(json)).await;
     }
@@ -111,7 +177,11 @@ async fn handle_connection(
                                 let _ = tx_for_task.send(serde_json::to_string(&msg).unwrap());
                             },
                             
-                            ClientMessage::FullGameState { my_board, opponent_board, scores, requester_id } => {
+                            ClientMessage::FullGameState { mut my_board, mut opponent_board, scores, requester_id } => {
+                                if let Err(e) = check_snapshot(&mut my_board, &state_for_task, my_id).and_then(|_| check_snapshot(&mut opponent_board, &state_for_task, requester_id)) {
+                                    println!("Snapshot de J{} rejeté: {}", my_id, e);
+                                    continue;
+                                }
                                 println!("Transfert Snapshot vers J{}...", requester_id);
                                 
                                 let sync_msg = ServerMessage::SyncState {
@@ -123,22 +193,55 @@ async fn handle_connection(
                                 let _ = tx_for_task.send(serde_json::to_string(&sync_msg).unwrap());
                             },
                             
-                            ClientMessage::PieceLocked { col, rot, axis_color_idx, sat_color_idx } => {
+                            ClientMessage::PieceLocked { col, rot, axis_color_idx, sat_color_idx, shape, checksum, fever_timer, frame } => {
+                                if checksum.is_some() {
+                                    if let Some(slot) = state_for_task.lock().unwrap().checksums.get_mut((my_id as usize).wrapping_sub(1)) { *slot = checksum; }
+                                }
                                 let server_msg = ServerMessage::OpponentAction {
-                                    player_id: my_id, col, rot, axis_color_idx, sat_color_idx
+                                    player_id: my_id, col, rot, axis_color_idx, sat_color_idx, shape, checksum, fever_timer, frame
                                 };
                                 let _ = tx_for_task.send(serde_json::to_string(&server_msg).unwrap());
                             },
+                            ClientMessage::MirrorChecksum { player_id, checksum, field } => {
+                                let own = state_for_task.lock().unwrap().checksums.get((player_id as usize).wrapping_sub(1)).copied().flatten();
+                                let Some(own) = own.filter(|own| own.pieces == checksum.pieces && own.value != checksum.value) else { continue };
+                                println!("Désynchronisation J{} à la pièce {}: {:016x} chez J{}, {:016x} dans son miroir chez J{}", player_id, own.pieces, own.value, player_id, checksum.value, my_id);
+                                println!("Miroir de J{} chez J{}:\n{}", player_id, my_id, field);
+                                let _ = tx_for_task.send(serde_json::to_string(&ServerMessage::RequestBoard { player_id }).unwrap());
+                            },
+                            ClientMessage::OwnBoard { mut board } => {
+                                if let Err(e) = check_snapshot(&mut board, &state_for_task, my_id) {
+                                    println!("Plateau de J{} rejeté: {}", my_id, e);
+                                    continue;
+                                }
+                                println!("Plateau réel de J{}:\n{}", my_id, board.to_notation());
+                                let _ = tx_for_task.send(serde_json::to_string(&ServerMessage::MirrorSync { player_id: my_id, board }).unwrap());
+                            },
+                            ClientMessage::FeverEnded => {
+                                let _ = tx_for_task.send(serde_json::to_string(&ServerMessage::FeverEnded { player_id: my_id }).unwrap());
+                            },
+                            ClientMessage::SendGarbage { amount } => {
+                                if amount > MAX_GARBAGE_SENT {
+                                    println!("Attaque de J{} ignorée: {} nuisances", my_id, amount);
+                                    continue;
+                                }
+                                let server_msg = ServerMessage::GarbageSent { player_id: my_id, amount };
+                                let _ = tx_for_task.send(serde_json::to_string(&server_msg).unwrap());
+                            },
                             ClientMessage::GameOver => {
                                 let _ = tx_for_task.send(serde_json::to_string(&ServerMessage::PlayerEliminated { player_id: my_id }).unwrap());
                             },
                             ClientMessage::RequestRestart => {
+                                let new_seed = rand::rng().random();
+                                let players;
                                 {
                                     let mut gs = state_for_task.lock().unwrap();
                                     gs.is_paused = false;
+                                    gs.seed = new_seed;
+                                    gs.checksums = [None; 2];
+                                    players = player_settings(&gs, new_seed);
                                 }
-                                let new_seed = rand::rng().random();
-                                let _ = tx_for_task.send(serde_json::to_string(&ServerMessage::Restart { new_seed }).unwrap());
+                                let _ = tx_for_task.send(serde_json::to_string(&ServerMessage::Restart { new_seed, players }).unwrap());
                             },
                             _ => {}
                         }
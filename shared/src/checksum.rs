@@ -0,0 +1,71 @@
+use crate::Board;
+use serde::{Deserialize, Serialize};
+
+/// A board reports its checksum when every `CHECKSUM_INTERVAL`-th piece spawns.
+pub const CHECKSUM_INTERVAL: u64 = 4;
+
+/// Hash of a board's field, preview, piece count and score, identical on every platform. `pieces`
+/// is the piece count it was taken at, so a mirror can compare once it has dealt as many.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardChecksum { pub pieces: u64, pub value: u64 }
+
+/// 64-bit FNV-1a.
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes { self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3); }
+    }
+}
+
+impl Board {
+    pub fn checksum(&self) -> BoardChecksum {
+        let mut hash = Fnv(0xCBF2_9CE4_8422_2325);
+        hash.write(&(self.width as u32).to_le_bytes());
+        hash.write(&(self.height as u32).to_le_bytes());
+        for cell in self.cells.iter().flatten() { hash.write(&[cell.map_or(0, |p| p.to_u8() + 1)]); }
+        for (axis, sat) in &self.preview { hash.write(&[axis.to_u8(), sat.to_u8()]); }
+        hash.write(&self.piece_count.to_le_bytes());
+        hash.write(&self.score.to_le_bytes());
+        BoardChecksum { pieces: self.piece_count, value: hash.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActivePuyo, BoardEvent, InputFrame, PuyoRng, PuyoType, Ruleset};
+
+    #[test]
+    fn golden_checksum() {
+        let board = Board::from_notation(Ruleset::tsu(), PuyoType::COLORS[..4].to_vec(), 9, "..RB..\nOOGY..").unwrap();
+        assert_eq!(board.checksum(), BoardChecksum { pieces: 0, value: GOLDEN_CHECKSUM });
+    }
+
+    #[test]
+    fn mirror_matches_reported_checksums() {
+        let colors = PuyoType::COLORS[..4].to_vec();
+        let mut board = Board::new(Ruleset::tsu(), colors.clone(), 77);
+        let mut mirror = Board::new(Ruleset::tsu(), colors, 77);
+        board.spawn_piece();
+        let mut rng = PuyoRng::new(1);
+        let mut compared = 0;
+        while board.state != crate::GameState::GameOver && board.piece_count < 60 {
+            let r = rng.below(6);
+            board.tick(InputFrame { left: r == 0, right: r == 1, rotate_cw: r == 2, hard_drop: r == 3, ..Default::default() });
+            for event in board.drain_events() {
+                let BoardEvent::PieceLocked { piece, checksum, frame, .. } = event else { continue };
+                mirror.next_piece();
+                mirror.set_frame(frame);
+                if let Some(checksum) = checksum {
+                    assert_eq!(mirror.checksum(), checksum);
+                    compared += 1;
+                }
+                mirror.apply_placement(&ActivePuyo { row: mirror.visible_top() as i32, ..piece });
+            }
+        }
+        assert!(compared >= 3);
+    }
+
+    const GOLDEN_CHECKSUM: u64 = 0x2B93_B50D_4D84_8A38;
+}
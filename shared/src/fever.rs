@@ -0,0 +1,78 @@
+use crate::PuyoType;
+
+/// Preset chain dropped on the fever field. Rows are listed top to bottom and sit on the floor,
+/// `A`, `B` and `C` standing for three distinct colors of the palette and `.` for an empty cell.
+/// Dropping one puyo of the right color in the right column fires the whole pattern.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeverPattern { pub chain: u32, pub rows: &'static [&'static str] }
+
+pub const FEVER_MIN_CHAIN: u32 = 3;
+pub const FEVER_MAX_CHAIN: u32 = 11;
+
+pub const FEVER_PATTERNS: [FeverPattern; 18] = [
+    FeverPattern { chain: 3, rows: &["....C.", "...CB.", "..CBBC", "..AAAB"] },
+    FeverPattern { chain: 3, rows: &["....B.", "....B.", "....C.", "...BC.", "...CA.", "...CA.", "...BA."] },
+    FeverPattern { chain: 4, rows: &[".A....", ".A.B..", "ACBB..", "CBCA..", "ACAA.."] },
+    FeverPattern { chain: 4, rows: &["....B.", "....B.", "....A.", "....C.", "....C.", "....BB", "....BC", "...ACB", "...AAB"] },
+    FeverPattern { chain: 5, rows: &[".A....", "BA....", "BB....", "CC....", "AC....", "CB....", "CC....", "AB....", "CB....", "CB...."] },
+    FeverPattern { chain: 5, rows: &["A.....", "BA....", "CC....", "CA....", "AC....", "AB....", "AC....", "BCC...", "BAA..."] },
+    FeverPattern { chain: 6, rows: &["..A...", "..A.A.", "..B.A.", "..BBC.", ".ACAA.", ".ACBAC", "CCAACC"] },
+    FeverPattern { chain: 6, rows: &["..A...", "..AB..", "..CC..", "..CAAB", "..ACBA", ".ACACA", ".ABCCA"] },
+    FeverPattern { chain: 7, rows: &["...A..", "...C..", ".B.C..", ".C.ACA", ".B.BAB", ".BCCBA", ".CBBAA", ".BACBB"] },
+    FeverPattern { chain: 7, rows: &["C.....", "C.....", "A.....", "A.....", "A.A...", "BAB...", "BCBA..", "ABAA..", "AABCC.", "BBCAC."] },
+    FeverPattern { chain: 8, rows: &["...B..", "...B..", "..AC..", "..AA.C", "..BCAA", "..CABA", ".BBCAB", ".BCCAB", "AABACB"] },
+    FeverPattern { chain: 8, rows: &[".....C", ".....C", "....AC", "....BB", "....BA", ".A..AB", ".ABABB", ".CBACA", ".CABAB", ".CABAB"] },
+    FeverPattern { chain: 9, rows: &[".....A", ".....B", ".....B", "..C.AC", "..A.BC", "B.A.AC", "B.ABAA", "A.CABB", "ACACAB", "ABBABC"] },
+    FeverPattern { chain: 9, rows: &["....BC", "....BC", "....BC", "..ABCA", "..CBBA", ".BCACA", ".BACAC", ".CBCBC", "CCBABC"] },
+    FeverPattern { chain: 10, rows: &["B....B", "B....C", "C....A", "CB...B", "CB...B", "AC...B", "ABCCAA", "BBCABA", "ACABCC", "ABAABC"] },
+    FeverPattern { chain: 10, rows: &[".B...C", ".B...C", ".C..BC", ".B..CA", ".A..AB", ".B..CB", "BB.BCB", "CACCBC", "CABCBB", "CACACA"] },
+    FeverPattern { chain: 11, rows: &["...A.C", "...A.B", "...A.B", ".ABBCB", ".CCCBA", ".BABAA", ".CBBCB", ".BCACC", "CCBCBB", "CAACBA"] },
+    FeverPattern { chain: 11, rows: &["....B.", ".B..CA", "CC..BC", "CA..BA", "BA..AA", "BC..CC", "AC.CAB", "CAAABC", "CCBBAC", "BAABAA"] },
+];
+
+impl FeverPattern {
+    /// Patterns of the requested length, clamped to the library range.
+    pub fn for_chain(chain: u32) -> Vec<&'static FeverPattern> {
+        let chain = chain.clamp(FEVER_MIN_CHAIN, FEVER_MAX_CHAIN);
+        FEVER_PATTERNS.iter().filter(|p| p.chain == chain).collect()
+    }
+
+    /// Builds a `width` x `height` field with the pattern on the floor, letters taken in order from
+    /// `colors`. Columns past `width` and rows above the top are cut off.
+    pub fn cells(&self, width: usize, height: usize, colors: &[PuyoType]) -> Vec<Vec<Option<PuyoType>>> {
+        let mut cells = vec![vec![None; width]; height];
+        for (i, line) in self.rows.iter().rev().enumerate() {
+            let Some(r) = height.checked_sub(i + 1) else { break };
+            for (c, ch) in line.chars().enumerate().take(width) {
+                if ch == '.' { continue; }
+                cells[r][c] = colors.get((ch as u8 - b'A') as usize).copied();
+            }
+        }
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Board, Ruleset};
+
+    /// Chain fired by dropping a single `color` puyo into `col`.
+    fn fired_chain(pattern: &FeverPattern, col: usize, color: PuyoType) -> usize {
+        let colors = [PuyoType::Red, PuyoType::Blue, PuyoType::Yellow];
+        let mut board = Board::new(Ruleset::tsu(), colors.to_vec(), 1);
+        board.cells = pattern.cells(board.width, board.height, &colors);
+        let top = (0..board.height).find(|&r| board.cells[r][col].is_some()).unwrap_or(board.height);
+        board.cells[top - 1][col] = Some(color);
+        board.simulate_chain().chain_length()
+    }
+
+    #[test]
+    fn every_pattern_fires_its_chain() {
+        for pattern in FEVER_PATTERNS.iter() {
+            let colors = [PuyoType::Red, PuyoType::Blue, PuyoType::Yellow];
+            let best = (0..6).flat_map(|col| colors.iter().map(move |c| (col, *c))).map(|(col, c)| fired_chain(pattern, col, c)).max();
+            assert_eq!(best, Some(pattern.chain as usize), "{:?}", pattern.rows);
+        }
+    }
+}
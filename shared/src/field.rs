@@ -0,0 +1,218 @@
+use crate::PuyoType;
+use std::ops::Range;
+
+/// Bits reserved per column, one more than the tallest field so shifts never cross columns.
+const STRIDE: usize = 16;
+pub const FIELD_MAX_WIDTH: usize = 128 / STRIDE;
+pub const FIELD_MAX_HEIGHT: usize = STRIDE - 1;
+
+/// Bitboard field with one `u128` plane per cell kind. Bit `col * 16 + y` is set when the
+/// cell at `row = height - 1 - y` holds that kind, rows being counted from the top like `Board::cells`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Field {
+    pub width: usize, pub height: usize,
+    planes: [u128; PuyoType::ALL.len()],
+    valid: u128,
+}
+
+impl Field {
+    pub fn new(width: usize, height: usize) -> Field {
+        assert!(width <= FIELD_MAX_WIDTH && height <= FIELD_MAX_HEIGHT, "field {}x{} exceeds {}x{}", width, height, FIELD_MAX_WIDTH, FIELD_MAX_HEIGHT);
+        let column = (1u128 << height) - 1;
+        let valid = (0..width).fold(0, |m, c| m | column << (c * STRIDE));
+        Field { width, height, planes: [0; PuyoType::ALL.len()], valid }
+    }
+
+    pub fn from_cells(cells: &[Vec<Option<PuyoType>>]) -> Field {
+        let mut field = Field::new(cells.first().map_or(0, Vec::len), cells.len());
+        for (r, row) in cells.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                if let Some(kind) = cell { field.planes[kind.to_u8() as usize] |= field.bit(r, c); }
+            }
+        }
+        field
+    }
+
+    pub fn write_cells(&self, cells: &mut [Vec<Option<PuyoType>>]) {
+        for (r, row) in cells.iter_mut().enumerate() {
+            for (c, cell) in row.iter_mut().enumerate() { *cell = self.get(r, c); }
+        }
+    }
+
+    pub fn to_cells(&self) -> Vec<Vec<Option<PuyoType>>> {
+        (0..self.height).map(|r| (0..self.width).map(|c| self.get(r, c)).collect()).collect()
+    }
+
+    pub fn bit(&self, row: usize, col: usize) -> u128 { 1 << (col * STRIDE + self.height - 1 - row) }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<PuyoType> {
+        let bit = self.bit(row, col);
+        PuyoType::ALL.iter().zip(self.planes.iter()).find(|(_, plane)| *plane & bit != 0).map(|(kind, _)| *kind)
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, cell: Option<PuyoType>) {
+        let bit = self.bit(row, col);
+        self.clear(bit);
+        if let Some(kind) = cell { self.planes[kind.to_u8() as usize] |= bit; }
+    }
+
+    pub fn plane(&self, kind: PuyoType) -> u128 { self.planes[kind.to_u8() as usize] }
+    pub fn occupied(&self) -> u128 { self.planes.iter().fold(0, |m, p| m | p) }
+    pub fn clear(&mut self, mask: u128) { for plane in self.planes.iter_mut() { *plane &= !mask; } }
+    pub fn fill(&mut self, mask: u128, kind: PuyoType) { self.clear(mask); self.planes[kind.to_u8() as usize] |= mask & self.valid; }
+
+    pub fn rows_mask(&self, rows: Range<usize>) -> u128 {
+        rows.filter(|r| *r < self.height).fold(0, |m, r| (0..self.width).fold(m, |m, c| m | self.bit(r, c)))
+    }
+
+    pub fn expand(&self, mask: u128) -> u128 {
+        (mask << 1 | mask >> 1 | mask << STRIDE | mask >> STRIDE) & self.valid
+    }
+
+    pub fn positions(&self, mask: u128) -> Vec<(usize, usize)> {
+        let mut out = Vec::with_capacity(mask.count_ones() as usize);
+        let mut rest = mask;
+        while rest != 0 {
+            let idx = rest.trailing_zeros() as usize;
+            out.push((self.height - 1 - idx % STRIDE, idx / STRIDE));
+            rest &= rest - 1;
+        }
+        out
+    }
+
+    pub fn apply_gravity(&mut self) -> bool {
+        let occupied = self.occupied();
+        let mut moved = false;
+        for c in 0..self.width {
+            let shift = c * STRIDE;
+            let column = (occupied >> shift) as u16;
+            if column & column.wrapping_add(1) == 0 { continue; }
+            moved = true;
+            for plane in self.planes.iter_mut() {
+                let packed = compress((*plane >> shift) as u16, column);
+                *plane = *plane & !(0xFFFF << shift) | (packed as u128) << shift;
+            }
+        }
+        moved
+    }
+
+    /// Connected same-color groups of at least `min_size` cells, grown only through `poppable` so
+    /// puyos outside it never join or pop.
+    pub fn groups(&self, min_size: usize, poppable: u128) -> Vec<(PuyoType, u128)> {
+        let mut out = Vec::new();
+        for kind in PuyoType::ALL.iter().filter(|k| k.is_color()) {
+            let plane = self.plane(*kind) & poppable;
+            let mut rest = plane;
+            while rest != 0 {
+                let mut group = rest & rest.wrapping_neg();
+                loop {
+                    let grown = (group | self.expand(group)) & plane;
+                    if grown == group { break; }
+                    group = grown;
+                }
+                rest &= !group;
+                if group.count_ones() as usize >= min_size { out.push((*kind, group)); }
+            }
+        }
+        out
+    }
+}
+
+fn compress(bits: u16, mask: u16) -> u16 {
+    let (mut out, mut out_bit, mut rest) = (0, 0, mask);
+    while rest != 0 {
+        let low = rest & rest.wrapping_neg();
+        if bits & low != 0 { out |= 1 << out_bit; }
+        out_bit += 1;
+        rest &= rest - 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Board, PuyoRng, Ruleset};
+
+    type Cells = Vec<Vec<Option<PuyoType>>>;
+
+    fn random_cells(rng: &mut PuyoRng, width: usize, height: usize) -> Cells {
+        let mut cell = || (rng.below(5) >= 2).then(|| PuyoType::ALL[rng.index(PuyoType::ALL.len())]);
+        (0..height).map(|_| (0..width).map(|_| cell()).collect()).collect()
+    }
+
+    /// Per-column gravity on the cell grid, as boards did before the bitboard.
+    fn naive_gravity(cells: &mut Cells) -> bool {
+        let (height, mut moved) = (cells.len(), false);
+        for c in 0..cells[0].len() {
+            let column: Vec<_> = (0..height).filter_map(|r| cells[r][c]).collect();
+            for (r, row) in cells.iter_mut().enumerate() {
+                let cell = (r + column.len()).checked_sub(height).map(|i| column[i]);
+                moved |= row[c] != cell;
+                row[c] = cell;
+            }
+        }
+        moved
+    }
+
+    /// Flood fill over same-color neighbours, groups sorted for comparison.
+    fn naive_groups(cells: &Cells, min_size: usize) -> Vec<(PuyoType, Vec<(usize, usize)>)> {
+        let (height, width) = (cells.len(), cells[0].len());
+        let mut seen = vec![vec![false; width]; height];
+        let mut out = Vec::new();
+        for (r, c) in (0..height).flat_map(|r| (0..width).map(move |c| (r, c))) {
+            let Some(kind) = cells[r][c].filter(|k| k.is_color() && !seen[r][c]) else { continue };
+            let (mut group, mut stack) = (Vec::new(), vec![(r, c)]);
+            seen[r][c] = true;
+            while let Some((r, c)) = stack.pop() {
+                group.push((r, c));
+                for (nr, nc) in [(r.wrapping_sub(1), c), (r + 1, c), (r, c.wrapping_sub(1)), (r, c + 1)] {
+                    if nr < height && nc < width && !seen[nr][nc] && cells[nr][nc] == Some(kind) { seen[nr][nc] = true; stack.push((nr, nc)); }
+                }
+            }
+            group.sort();
+            if group.len() >= min_size { out.push((kind, group)); }
+        }
+        out.sort_by_key(|(kind, group)| (kind.to_u8(), group.clone()));
+        out
+    }
+
+    #[test]
+    fn bitboard_matches_the_cell_grid() {
+        let mut rng = PuyoRng::new(9);
+        for i in 0..2000 {
+            let (width, height) = if i % 2 == 0 { (FIELD_MAX_WIDTH, FIELD_MAX_HEIGHT) } else { (1 + rng.index(FIELD_MAX_WIDTH), 1 + rng.index(FIELD_MAX_HEIGHT)) };
+            let mut cells = random_cells(&mut rng, width, height);
+            let mut field = Field::from_cells(&cells);
+            assert_eq!(field.to_cells(), cells);
+            let min_size = 2 + i % 3;
+            let mut groups: Vec<_> = field.groups(min_size, field.valid).into_iter().map(|(kind, mask)| {
+                let mut positions = field.positions(mask);
+                positions.sort();
+                (kind, positions)
+            }).collect();
+            groups.sort_by_key(|(kind, group)| (kind.to_u8(), group.clone()));
+            assert_eq!(groups, naive_groups(&cells, min_size), "{:?}", cells);
+            assert_eq!(field.apply_gravity(), naive_gravity(&mut cells));
+            assert_eq!(field.to_cells(), cells);
+        }
+    }
+
+    fn column(top: &[&str]) -> String {
+        let support = ["B.....", "Y....."].iter().cycle().take(13 - top.len());
+        top.iter().chain(support).copied().collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn hidden_puyos_never_join_groups() {
+        let mut board = Board::from_notation(Ruleset::tsu(), PuyoType::COLORS.to_vec(), 1, &column(&["R.....", "R.....", "R.....", "R....."])).unwrap();
+        assert_eq!(board.visible_top(), 2);
+        let cells = board.cells.clone();
+        assert!(!board.check_matches(), "three visible reds and a hidden one must not pop");
+        assert_eq!(board.cells, cells);
+        let mut board = Board::from_notation(Ruleset::tsu(), PuyoType::COLORS.to_vec(), 1, &column(&["R.....", "R.....", "R.....", "R.....", "R....."])).unwrap();
+        assert!(board.check_matches());
+        assert_eq!(board.cells[1][0], Some(PuyoType::Red));
+        assert_eq!(board.score, 40);
+    }
+}
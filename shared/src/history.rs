@@ -0,0 +1,113 @@
+use crate::Board;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Undo/redo of placements for practice: the board as it was when each of the last `limit` placed
+/// pieces spawned, plus the boards undone since the last placement.
+#[derive(Clone, Debug)]
+pub(crate) struct PlacementHistory {
+    limit: usize,
+    turn_start: Option<Board>,
+    undo: VecDeque<Board>,
+    redo: Vec<Board>,
+}
+
+impl Board {
+    /// Starts keeping up to `limit` placements, dropping any history kept so far.
+    pub fn enable_history(&mut self, limit: usize) {
+        self.history = Some(Arc::new(PlacementHistory { limit: limit.max(1), turn_start: None, undo: VecDeque::new(), redo: Vec::new() }));
+        if self.active_piece.is_some() { self.record_turn_start(); }
+    }
+
+    pub fn can_undo(&self) -> bool { self.history.as_ref().is_some_and(|h| !h.undo.is_empty()) }
+    pub fn can_redo(&self) -> bool { self.history.as_ref().is_some_and(|h| !h.redo.is_empty()) }
+
+    /// Goes back to the spawn of the last placed piece, restoring field, queue, score and timers.
+    pub fn undo(&mut self) -> bool {
+        let Some(previous) = self.history_mut().and_then(|h| h.undo.pop_back()) else { return false };
+        let current = self.current_turn();
+        self.history_mut().unwrap().redo.push(current);
+        self.restore(previous);
+        true
+    }
+
+    /// Replays an undone placement, back to where the board was before the matching `undo`.
+    pub fn redo(&mut self) -> bool {
+        let Some(next) = self.history_mut().and_then(|h| h.redo.pop()) else { return false };
+        let current = self.current_turn();
+        self.history_mut().unwrap().undo.push_back(current);
+        self.restore(next);
+        true
+    }
+
+    pub(crate) fn record_turn_start(&mut self) {
+        if self.history.is_none() { return; }
+        let snapshot = self.snapshot();
+        self.history_mut().unwrap().turn_start = Some(snapshot);
+    }
+
+    pub(crate) fn record_placement(&mut self) {
+        let Some(history) = self.history_mut() else { return };
+        let Some(turn_start) = history.turn_start.take() else { return };
+        history.undo.push_back(turn_start);
+        if history.undo.len() > history.limit { history.undo.pop_front(); }
+        history.redo.clear();
+    }
+
+    /// Copies of the board share the history until one of them writes to it.
+    fn history_mut(&mut self) -> Option<&mut PlacementHistory> { self.history.as_mut().map(Arc::make_mut) }
+
+    /// The spawn of the piece in hand, or the board itself while no piece is held.
+    fn current_turn(&mut self) -> Board {
+        match self.history_mut().and_then(|h| h.turn_start.take()) {
+            Some(turn_start) => turn_start,
+            None => self.snapshot(),
+        }
+    }
+
+    fn snapshot(&mut self) -> Board {
+        let history = self.history.take();
+        let mut snapshot = self.clone();
+        self.history = history;
+        snapshot.events.clear();
+        snapshot
+    }
+
+    fn restore(&mut self, board: Board) {
+        let mut history = self.history.take();
+        if let Some(history) = history.as_mut().map(Arc::make_mut) { history.turn_start = board.active_piece.is_some().then(|| board.clone()); }
+        *self = board;
+        self.history = history;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Board, InputFrame, PuyoType, Ruleset};
+
+    fn next_turn(board: &mut Board) {
+        board.hard_drop();
+        while board.active_piece.is_none() { board.tick(InputFrame::default()); }
+    }
+
+    #[test]
+    fn undo_and_redo_placements() {
+        let mut board = Board::new(Ruleset::tsu(), PuyoType::COLORS[..4].to_vec(), 3);
+        board.spawn_piece();
+        board.enable_history(2);
+        let start = board.clone();
+        next_turn(&mut board);
+        let second = board.clone();
+        next_turn(&mut board);
+        next_turn(&mut board);
+        assert!(board.undo() && board.undo());
+        assert_eq!((&board.cells, &board.preview, board.piece_count), (&second.cells, &second.preview, second.piece_count));
+        assert!(!board.undo(), "only the last two placements are kept");
+        assert_ne!(board.cells, start.cells);
+        assert!(board.redo() && board.redo() && !board.redo());
+        assert_eq!(board.piece_count, 4);
+        board.undo();
+        next_turn(&mut board);
+        assert!(!board.can_redo());
+    }
+}
@@ -1,22 +1,43 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use rand::Rng;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
 
-fn default_rng() -> rand::rngs::StdRng {
-    use rand::SeedableRng;
-    rand::rngs::StdRng::seed_from_u64(0)
-}
+mod checksum;
+mod fever;
+mod field;
+mod history;
+mod notation;
+mod puzzle;
+mod queue;
+mod rng;
+mod ruleset;
+mod validate;
+pub use checksum::{BoardChecksum, CHECKSUM_INTERVAL};
+pub use fever::{FeverPattern, FEVER_MAX_CHAIN, FEVER_MIN_CHAIN, FEVER_PATTERNS};
+pub use field::{Field, FIELD_MAX_HEIGHT, FIELD_MAX_WIDTH};
+pub use notation::NotationError;
+pub use validate::BoardError;
+pub use puzzle::{Objective, Puzzle, PuzzleProgress};
+pub use queue::{NoColors, Pair, PairQueue, PieceQueue, QueueKind, RandomQueue, ScriptedQueue, TsuQueue, TSU_TABLE_PAIRS};
+pub use rng::{PuyoRng, UnknownRngVersion};
+pub use ruleset::{Handicap, Ruleset};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ClientMessage {
     Join { name: String },
-    PieceLocked { col: i32, rot: usize, axis_color_idx: u8, sat_color_idx: u8 },
+    PieceLocked { col: i32, rot: usize, axis_color_idx: u8, sat_color_idx: u8, #[serde(default)] shape: PieceShape, #[serde(default)] checksum: Option<BoardChecksum>, #[serde(default)] fever_timer: u32, #[serde(default)] frame: u64 },
+    FeverEnded,
+    /// Checksum of the sender's mirror of `player_id`, with its field in text notation for desync reports.
+    MirrorChecksum { player_id: u8, checksum: BoardChecksum, field: String },
+    /// Answer to `RequestBoard`.
+    OwnBoard { board: Box<Board> },
+    SendGarbage { amount: u32 },
     GameOver,
     RequestRestart,
     TogglePause, 
     FullGameState { 
-        my_board: Board, 
-        opponent_board: Board, 
+        my_board: Box<Board>, 
+        opponent_board: Box<Board>, 
         scores: (i32, i32),
         requester_id: u8 
     }
@@ -24,124 +45,322 @@ pub enum ClientMessage {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ServerMessage {
-    Welcome { player_id: u8, random_seed: u64 },
+    /// `players[id - 1]` holds the settings of player `id`.
+    Welcome { player_id: u8, random_seed: u64, ruleset: Box<Ruleset>, players: Vec<PlayerSettings> },
     GameStart,
-    OpponentAction { player_id: u8, col: i32, rot: usize, axis_color_idx: u8, sat_color_idx: u8 },
+    /// `fever_timer` and `frame` are the sender's when the piece locked, mirrors taking them over.
+    OpponentAction { player_id: u8, col: i32, rot: usize, axis_color_idx: u8, sat_color_idx: u8, #[serde(default)] shape: PieceShape, #[serde(default)] checksum: Option<BoardChecksum>, #[serde(default)] fever_timer: u32, #[serde(default)] frame: u64 },
+    /// Ends the fever of the mirrors of `player_id`, which only the real board's clock decides.
+    FeverEnded { player_id: u8 },
+    GarbageSent { player_id: u8, amount: u32 },
     PlayerEliminated { player_id: u8 },
-    Restart { new_seed: u64 },
+    Restart { new_seed: u64, players: Vec<PlayerSettings> },
     GameStateChange { paused: bool },
     OpponentDisconnected,
     RequestSnapshot { requester_id: u8 },
+    /// Asks `player_id` for its own board after its opponent's mirror of it drifted.
+    RequestBoard { player_id: u8 },
+    /// Replaces the mirror of `player_id` with its real board.
+    MirrorSync { player_id: u8, board: Box<Board> },
     
     SyncState { 
-        my_board: Board,       
-        opponent_board: Board, 
+        my_board: Box<Board>,       
+        opponent_board: Box<Board>, 
         scores: (i32, i32),
         target_player_id: u8 
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerSettings { pub handicap: Handicap, pub colors: Vec<PuyoType> }
+
+impl PlayerSettings {
+    pub fn new(ruleset: &Ruleset, handicap: Handicap, seed: u64) -> PlayerSettings {
+        let colors = PuyoType::palette(handicap.apply(ruleset).color_count, seed);
+        PlayerSettings { handicap, colors }
+    }
+
+    pub fn board(&self, ruleset: &Ruleset, seed: u64) -> Board { Board::new(self.handicap.apply(ruleset), self.colors.clone(), seed) }
+
+    /// Repairs a snapshot of this player's board, failing if it plays by other rules or colors.
+    pub fn repair(&self, ruleset: &Ruleset, board: &mut Board) -> Result<Vec<BoardError>, BoardError> { board.repair(&self.handicap.apply(ruleset), &self.colors) }
+}
+
 pub const CELL_SIZE: f32 = 40.0; 
-pub const GRID_WIDTH: usize = 6;
-pub const GRID_HEIGHT: usize = 13;
-pub const VISIBLE_ROW_OFFSET: usize = 1;
-pub const MAX_LOCK_TIME: f32 = 0.5;
-pub const MAX_LOCK_DELAY_MOVES: u32 = 15;
-pub const MAX_TOTAL_GROUND_TIME: f32 = 2.0;
-pub const DAS_DELAY: f32 = 0.2;
-pub const DAS_SPEED: f32 = 0.05;
-pub const SOFT_DROP_SPEED: f32 = 0.05;
-
-const CHAIN_POWERS: [u32; 20] = [0, 0, 8, 16, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448, 480, 512];
-const COLOR_BONUS: [u32; 6] = [0, 0, 3, 6, 12, 24];
-const GROUP_BONUS: [u32; 8] = [0, 2, 3, 4, 5, 6, 7, 10];
+pub const FRAME_RATE: u32 = 60;
+pub const FRAME_TIME: f32 = 1.0 / FRAME_RATE as f32;
 
 #[derive(Clone, Copy, PartialEq, Debug, Eq, Hash, Serialize, Deserialize)]
-pub enum PuyoType { Red, Blue, Yellow, Green, Purple }
+pub enum PuyoType {
+    Red, Blue, Yellow, Green, Purple, Garbage,
+    /// Nuisance that takes two adjacent pops, the first turning it into plain nuisance.
+    Hard,
+    /// Cleared like nuisance, scoring `Ruleset::point_score` when it goes.
+    Point,
+    /// Cleared like nuisance, adding `Ruleset::sun_garbage` to the attack when it goes.
+    Sun,
+}
 
 impl PuyoType {
-    pub fn random_with_seed<R: Rng>(rng: &mut R) -> PuyoType {
-        match rng.gen_range(0..5) { 0 => PuyoType::Red, 1 => PuyoType::Blue, 2 => PuyoType::Yellow, 3 => PuyoType::Green, _ => PuyoType::Purple }
+    pub const COLORS: [PuyoType; 5] = [PuyoType::Red, PuyoType::Blue, PuyoType::Yellow, PuyoType::Green, PuyoType::Purple];
+    pub const ALL: [PuyoType; 9] = [
+        PuyoType::Red, PuyoType::Blue, PuyoType::Yellow, PuyoType::Green, PuyoType::Purple,
+        PuyoType::Garbage, PuyoType::Hard, PuyoType::Point, PuyoType::Sun,
+    ];
+
+    pub fn random_from(rng: &mut PuyoRng, colors: &[PuyoType]) -> PuyoType {
+        colors[rng.index(colors.len())]
     }
-    pub fn to_u8(&self) -> u8 { match self { PuyoType::Red => 0, PuyoType::Blue => 1, PuyoType::Yellow => 2, PuyoType::Green => 3, PuyoType::Purple => 4 } }
-    pub fn from_u8(val: u8) -> PuyoType { match val { 0 => PuyoType::Red, 1 => PuyoType::Blue, 2 => PuyoType::Yellow, 3 => PuyoType::Green, _ => PuyoType::Purple } }
+    pub fn palette(count: usize, seed: u64) -> Vec<PuyoType> {
+        let mut colors = PuyoType::COLORS.to_vec();
+        PuyoRng::new(seed).shuffle(&mut colors);
+        colors.truncate(count.clamp(3, PuyoType::COLORS.len()));
+        colors
+    }
+    pub fn to_u8(&self) -> u8 { match self { PuyoType::Red => 0, PuyoType::Blue => 1, PuyoType::Yellow => 2, PuyoType::Green => 3, PuyoType::Purple => 4, PuyoType::Garbage => 5, PuyoType::Hard => 6, PuyoType::Point => 7, PuyoType::Sun => 8 } }
+    pub fn from_u8(val: u8) -> PuyoType { match val { 0 => PuyoType::Red, 1 => PuyoType::Blue, 2 => PuyoType::Yellow, 3 => PuyoType::Green, 5 => PuyoType::Garbage, 6 => PuyoType::Hard, 7 => PuyoType::Point, 8 => PuyoType::Sun, _ => PuyoType::Purple } }
+    pub fn is_color(&self) -> bool { !matches!(self, PuyoType::Garbage | PuyoType::Hard | PuyoType::Point | PuyoType::Sun) }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PieceShape {
+    #[default] Pair,
+    /// L of three, the axis color stacked vertically and the satellite color on the side.
+    TripleVertical,
+    /// L of three, the axis color laid horizontally and the satellite color on top.
+    TripleHorizontal,
+    /// 2x2 with the axis color on the left column and the satellite color on the right.
+    Quad,
+    /// 2x2 of the axis color only, rotating cycles its color through the palette.
+    BigQuad,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+/// How blocked rotations are resolved.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum RotationSystem {
+    /// Push away from a blocked side or floor kick a limited number of times per piece, a boxed-in
+    /// pair quick turning only when rotation is pressed again within `Ruleset::quick_turn_frames`.
+    #[default] Tsu,
+    /// Kick left, right then up, a boxed-in pair quick turning straight away.
+    Legacy,
+}
+
+impl PieceShape {
+    pub fn is_quad(&self) -> bool { matches!(self, PieceShape::Quad | PieceShape::BigQuad) }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub struct ActivePuyo {
     pub row: i32, pub col: i32, pub rotation: usize, pub axis_type: PuyoType, pub sat_type: PuyoType,
+    #[serde(default)] pub shape: PieceShape,
 }
 
 impl ActivePuyo {
-    pub fn get_positions(&self) -> [(i32, i32); 2] {
-        let (dr, dc) = match self.rotation { 0 => (-1, 0), 1 => (0, 1), 2 => (1, 0), 3 => (0, -1), _ => (-1, 0) };
-        [(self.row, self.col), (self.row + dr, self.col + dc)]
+    pub fn cells(&self) -> Vec<((i32, i32), PuyoType)> {
+        let (a, b) = (self.axis_type, self.sat_type);
+        let rotate = |offset: (i32, i32)| (0..self.rotation % 4).fold(offset, |(dr, dc), _| (dc, -dr));
+        let at = |(dr, dc): (i32, i32), p_type| ((self.row + dr, self.col + dc), p_type);
+        match self.shape {
+            PieceShape::Pair => vec![at((0, 0), a), at(rotate((-1, 0)), b)],
+            PieceShape::TripleVertical => vec![at((0, 0), a), at(rotate((-1, 0)), a), at(rotate((0, 1)), b)],
+            PieceShape::TripleHorizontal => vec![at((0, 0), a), at(rotate((0, 1)), a), at(rotate((-1, 0)), b)],
+            PieceShape::Quad | PieceShape::BigQuad => {
+                let square = [(0, 0), (-1, 0), (-1, 1), (0, 1)];
+                let colors = if self.shape == PieceShape::Quad { [a, a, b, b] } else { [a; 4] };
+                (0..4).map(|i| at(square[i], colors[(i + 4 - self.rotation % 4) % 4])).collect()
+            }
+        }
     }
+
+    pub fn get_positions(&self) -> Vec<(i32, i32)> { self.cells().into_iter().map(|(pos, _)| pos).collect() }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub left: bool, pub right: bool, pub soft_drop: bool,
+    pub hard_drop: bool, pub rotate_cw: bool, pub rotate_ccw: bool,
 }
 
-#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
-pub enum GameState { Playing, ResolvingMatches, GameOver, Paused }
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PoppedGroup { pub color: PuyoType, pub positions: Vec<(usize, usize)> }
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BoardEvent {
+    PieceSpawned { piece: ActivePuyo },
+    PieceMoved { piece: ActivePuyo },
+    PieceRotated { piece: ActivePuyo },
+    /// `checksum` is the board's as the piece spawned, every `CHECKSUM_INTERVAL` pieces.
+    PieceLocked { piece: ActivePuyo, checksum: Option<BoardChecksum>, fever_timer: u32, frame: u64 },
+    /// `specials_cleared` lists point and sun puyos taken by the pops, hardened cells are not reported.
+    GroupsPopped { groups: Vec<PoppedGroup>, garbage_cleared: Vec<(usize, usize)>, specials_cleared: Vec<PoppedGroup> },
+    ChainStep { chain: u32, points: u32, garbage: u32 },
+    GarbageDropped { amount: u32 },
+    AllClear,
+    FeverStarted { chain: u32 },
+    FeverEnded,
+    PuzzleFinished { solved: bool },
+    GameOver,
+}
+
+/// Scoring of one chain step: `points = 10 * cleared * multiplier + bonus_points`, the multiplier being
+/// the sum of the three bonuses clamped to `1..=999`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ChainStepReport {
+    pub chain: u32, pub cleared: u32,
+    pub chain_power: u32, pub color_bonus: u32, pub group_bonus: u32, pub multiplier: u32,
+    pub bonus_points: u32, pub points: u32, pub garbage: u32,
+}
+
+pub const CHAIN_HISTORY_LEN: usize = 16;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SimulatedStep { pub groups: Vec<PoppedGroup>, pub points: u32, pub garbage: u32 }
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ChainReport {
+    pub steps: Vec<SimulatedStep>,
+    pub score: u32, pub garbage: u32, pub all_clear: bool,
+    pub field: Vec<Vec<Option<PuyoType>>>,
+}
+
+impl ChainReport {
+    pub fn chain_length(&self) -> usize { self.steps.len() }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum GameState { Playing, ResolvingMatches, Fever, FeverResolving, GameOver, Paused }
+
+impl GameState {
+    /// Whether a piece is being controlled, on the normal or the fever field.
+    pub fn is_playing(&self) -> bool { matches!(self, GameState::Playing | GameState::Fever) }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)] 
 pub struct Board {
     pub width: usize, pub height: usize,
+    pub ruleset: Ruleset,
+    pub colors: Vec<PuyoType>,
     pub cells: Vec<Vec<Option<PuyoType>>>,
     pub active_piece: Option<ActivePuyo>,
-    pub next_types: (PuyoType, PuyoType),
-    pub next_next_types: (PuyoType, PuyoType),
+    pub queue: PairQueue,
+    pub preview: VecDeque<Pair>,
+    pub piece_count: u64,
     pub score: i32,
     pub state: GameState,
     #[serde(skip)] pub previous_state: Option<Box<GameState>>,
-    pub lock_timer: f32, pub total_ground_timer: f32, pub is_touching_ground: bool,
+    pub frame: u64, pub fall_timer: u32, pub resolve_timer: u32,
+    pub das_left: u32, pub das_right: u32, pub soft_drop_timer: u32,
+    pub lock_timer: u32, pub total_ground_timer: u32, pub is_touching_ground: bool,
     pub ground_move_count: u32, pub lowest_row_reached: i32, pub chain_count: u32,
-    #[serde(skip, default = "default_rng")] rng: rand::rngs::StdRng,
+    pub floor_kicks: u32, pub quick_turn_frame: Option<u64>,
+    pub pending_garbage: u32, pub garbage_dropped: bool,
+    pub target_point: u32, pub leftover_points: u32, pub all_clear: bool,
+    /// Fever gauge, timer in frames, length of the next preset chain and the normal field kept aside.
+    pub fever_gauge: u32, pub fever_timer: u32, pub fever_chain: u32,
+    pub saved_cells: Option<Vec<Vec<Option<PuyoType>>>>,
+    pub puzzle: Option<PuzzleProgress>,
+    /// Steps of the chain in progress, then the last `CHAIN_HISTORY_LEN` finished chains, oldest first.
+    pub chain_steps: Vec<ChainStepReport>, pub chain_history: VecDeque<Vec<ChainStepReport>>,
+    #[serde(skip)] events: Vec<BoardEvent>,
+    #[serde(skip)] history: Option<Arc<history::PlacementHistory>>,
+    #[serde(skip)] spawn_checksum: Option<BoardChecksum>,
+    garbage_rng: PuyoRng,
 }
 
 impl Board {
-    pub fn new(width: usize, height: usize, seed: u64) -> Board {
-        use rand::SeedableRng;
-        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
-        let n1 = (PuyoType::random_with_seed(&mut rng), PuyoType::random_with_seed(&mut rng));
-        let n2 = (PuyoType::random_with_seed(&mut rng), PuyoType::random_with_seed(&mut rng));
+    /// Without colors the queue deals nothing and the first spawn ends the game.
+    pub fn new(ruleset: Ruleset, colors: Vec<PuyoType>, seed: u64) -> Board {
+        let queue = PairQueue::new(ruleset.queue, &colors, seed).unwrap_or_else(|_| PairQueue::Scripted(ScriptedQueue::new(Vec::new(), false)));
+        Board::with_queue(ruleset, colors, queue, seed)
+    }
 
+    pub fn with_queue(ruleset: Ruleset, colors: Vec<PuyoType>, mut queue: PairQueue, seed: u64) -> Board {
+        let garbage_rng = PuyoRng::new(seed.rotate_left(32));
+        let preview = (0..ruleset.preview_len.max(1)).map_while(|_| queue.next_pair()).collect();
+        let (width, height) = Board::size_for(&ruleset);
         Board {
-            width, height, cells: vec![vec![None; width]; height], active_piece: None,
-            next_types: n1, next_next_types: n2, score: 0, state: GameState::Playing,
-            previous_state: None, lock_timer: 0.0, total_ground_timer: 0.0, is_touching_ground: false,
-            ground_move_count: 0, lowest_row_reached: -100, chain_count: 0, rng,
+            width, height, colors, cells: vec![vec![None; width]; height], active_piece: None,
+            queue, preview, piece_count: 0, score: 0, state: GameState::Playing,
+            previous_state: None, frame: 0, fall_timer: 0, resolve_timer: 0, das_left: 0, das_right: 0, soft_drop_timer: 0,
+            lock_timer: 0, total_ground_timer: 0, is_touching_ground: false,
+            ground_move_count: 0, lowest_row_reached: -100, chain_count: 0, floor_kicks: 0, quick_turn_frame: None,
+            pending_garbage: 0, garbage_dropped: false,
+            target_point: ruleset.target_point, leftover_points: 0, all_clear: false,
+            fever_gauge: 0, fever_timer: 0, fever_chain: ruleset.fever_start_chain, saved_cells: None, puzzle: None,
+            chain_steps: Vec::new(), chain_history: VecDeque::new(), events: Vec::new(), history: None, spawn_checksum: None,
+            ruleset, garbage_rng,
         }
     }
 
     pub fn spawn_piece(&mut self) {
-        if self.cells[VISIBLE_ROW_OFFSET][2].is_some() { self.state = GameState::GameOver; return; }
-        let (c1, c2) = self.next_types;
-        self.next_types = self.next_next_types;
-        self.next_next_types = (PuyoType::random_with_seed(&mut self.rng), PuyoType::random_with_seed(&mut self.rng));
-        let new_piece = ActivePuyo { row: 1, col: 2, rotation: 0, axis_type: c1, sat_type: c2 };
-        if self.check_collision(&new_piece) { self.state = GameState::GameOver; } else {
+        let Some(((c1, c2), shape)) = self.next_piece() else { self.set_game_over(); return; };
+        let (row, col) = self.spawn_position();
+        self.start_piece(ActivePuyo { row, col, rotation: 0, axis_type: c1, sat_type: c2, shape });
+    }
+
+    /// Deals the next pair and shape as spawning does. Mirrors of remote boards call it for each
+    /// placement so their queue and piece count keep pace.
+    pub fn next_piece(&mut self) -> Option<(Pair, PieceShape)> {
+        let pair = self.preview.pop_front()?;
+        if let Some(next) = self.queue.next_pair() { self.preview.push_back(next); }
+        let shape = self.ruleset.drop_set.get(self.piece_count as usize % self.ruleset.drop_set.len().max(1)).copied().unwrap_or_default();
+        self.piece_count += 1;
+        Some((pair, shape))
+    }
+
+    /// First row shown and able to pop, the rows above it being the ghost rows then the hidden rows.
+    pub fn visible_top(&self) -> usize { (self.ruleset.ghost_rows + self.ruleset.hidden_rows).min(self.height - 2) }
+    pub fn ghost_rows(&self) -> usize { self.ruleset.ghost_rows.min(self.visible_top()) }
+    pub fn visible_rows(&self) -> usize { self.height - self.visible_top() }
+
+    /// The axis spawns on the top visible row of the ruleset's spawn column.
+    pub fn spawn_position(&self) -> (i32, i32) { (self.visible_top() as i32, self.ruleset.spawn_col.min(self.width - 1) as i32) }
+
+    pub fn death_cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.ruleset.death_cells.iter().map(|(r, c)| (r + self.visible_top(), *c)).filter(|(r, c)| *r < self.height && *c < self.width)
+    }
+
+    fn is_spawn_blocked(&self, piece: &ActivePuyo) -> bool {
+        self.death_cells().any(|(r, c)| self.cells[r][c].is_some()) || self.check_collision(piece)
+    }
+
+    /// Fever ends instead of the game when the fever field is topped out.
+    fn start_piece(&mut self, new_piece: ActivePuyo) {
+        if self.in_fever() && self.is_spawn_blocked(&new_piece) { self.end_fever(); }
+        if self.is_spawn_blocked(&new_piece) { self.set_game_over(); } else {
+            self.events.push(BoardEvent::PieceSpawned { piece: new_piece.clone() });
             self.lowest_row_reached = new_piece.row; self.active_piece = Some(new_piece);
-            self.lock_timer = 0.0; self.total_ground_timer = 0.0; self.is_touching_ground = false;
-            self.ground_move_count = 0; self.chain_count = 0;
+            self.lock_timer = 0; self.total_ground_timer = 0; self.is_touching_ground = false; self.fall_timer = 0;
+            self.ground_move_count = 0; self.chain_count = 0; self.garbage_dropped = false;
+            self.floor_kicks = 0; self.quick_turn_frame = None;
+            self.spawn_checksum = self.piece_count.is_multiple_of(CHECKSUM_INTERVAL).then(|| self.checksum());
+            self.record_turn_start();
         }
     }
 
+    fn set_game_over(&mut self) {
+        self.fail_puzzle();
+        self.state = GameState::GameOver;
+        self.events.push(BoardEvent::GameOver);
+    }
+
+    pub fn drain_events(&mut self) -> Vec<BoardEvent> { std::mem::take(&mut self.events) }
+
     pub fn get_ghost_piece(&self) -> Option<ActivePuyo> {
         let mut ghost = self.active_piece.clone()?;
         while !self.check_collision(&ghost) { ghost.row += 1; }
         ghost.row -= 1; Some(ghost)
     }
 
-    pub fn check_collision(&self, piece: &ActivePuyo) -> bool {
-        for (r, c) in piece.get_positions().iter() {
-            if *c < 0 || *c >= self.width as i32 || *r >= self.height as i32 { return true; }
-            if *r >= 0 && self.cells[*r as usize][*c as usize].is_some() { return true; }
-        }
-        false
+    pub fn check_collision(&self, piece: &ActivePuyo) -> bool { piece.get_positions().into_iter().any(|pos| self.is_blocked(pos)) }
+
+    fn is_blocked(&self, (r, c): (i32, i32)) -> bool {
+        c < 0 || c >= self.width as i32 || r >= self.height as i32 || (r >= 0 && self.cells[r as usize][c as usize].is_some())
     }
 
     fn reset_lock_if_needed(&mut self) {
-        if self.is_touching_ground && self.ground_move_count < MAX_LOCK_DELAY_MOVES {
-            self.lock_timer = 0.0; self.ground_move_count += 1;
+        if self.is_touching_ground && self.ground_move_count < self.ruleset.lock_delay_moves {
+            self.lock_timer = 0; self.ground_move_count += 1;
         }
     }
 
@@ -149,6 +368,7 @@ impl Board {
         if let Some(mut piece) = self.active_piece.take() {
             piece.col += dx;
             if self.check_collision(&piece) { piece.col -= dx; } else {
+                self.events.push(BoardEvent::PieceMoved { piece: piece.clone() });
                 self.active_piece = Some(piece); self.reset_lock_if_needed(); return;
             }
             self.active_piece = Some(piece);
@@ -159,34 +379,75 @@ impl Board {
         if let Some(mut piece) = self.active_piece.take() {
             let (old_rot, old_col, old_row) = (piece.rotation, piece.col, piece.row);
             piece.rotation = (piece.rotation + direction) % 4;
-            if self.check_collision(&piece) {
-                piece.col -= 1;
-                if self.check_collision(&piece) {
-                    piece.col = old_col + 1;
-                    if self.check_collision(&piece) {
-                        piece.col = old_col; piece.row -= 1;
-                        if self.check_collision(&piece) {
-                            piece.row = old_row; piece.col = old_col; piece.rotation = old_rot;
-                            let quick_rot = (old_rot + 2) % 4; piece.rotation = quick_rot;
-                            if self.check_collision(&piece) { piece.rotation = old_rot; }
-                        }
-                    }
+            if piece.shape.is_quad() {
+                if piece.shape == PieceShape::BigQuad && !self.colors.is_empty() {
+                    let idx = self.colors.iter().position(|c| *c == piece.axis_type).unwrap_or(0);
+                    let step = if direction % 4 == 1 { 1 } else { self.colors.len() - 1 };
+                    piece.axis_type = self.colors[(idx + step) % self.colors.len()];
                 }
+            } else if self.check_collision(&piece) {
+                piece = match self.ruleset.rotation {
+                    RotationSystem::Tsu => self.kick_tsu(piece, old_rot),
+                    RotationSystem::Legacy => self.kick_legacy(piece, old_rot),
+                };
+            }
+            if piece.rotation != old_rot || piece.col != old_col || piece.row != old_row {
+                self.events.push(BoardEvent::PieceRotated { piece: piece.clone() });
+                self.reset_lock_if_needed();
             }
-            if piece.rotation != old_rot || piece.col != old_col || piece.row != old_row { self.reset_lock_if_needed(); }
             self.active_piece = Some(piece);
         }
     }
 
-    pub fn hard_drop(&mut self) {
-        if let Some(mut piece) = self.active_piece.take() {
-            loop {
-                piece.row += 1;
-                if self.check_collision(&piece) { piece.row -= 1; break; }
+    fn kick_legacy(&self, mut piece: ActivePuyo, old_rot: usize) -> ActivePuyo {
+        let (old_col, old_row) = (piece.col, piece.row);
+        for (dr, dc) in [(0, -1), (0, 1), (-1, 0)] {
+            piece.row = old_row + dr; piece.col = old_col + dc;
+            if !self.check_collision(&piece) { return piece; }
+        }
+        piece.row = old_row; piece.col = old_col; piece.rotation = old_rot;
+        if piece.shape == PieceShape::Pair {
+            piece.rotation = (old_rot + 2) % 4;
+            if self.check_collision(&piece) { piece.rotation = old_rot; }
+        }
+        piece
+    }
+
+    fn kick_tsu(&mut self, mut piece: ActivePuyo, old_rot: usize) -> ActivePuyo {
+        let blocked: Vec<(i32, i32)> = piece.get_positions().into_iter().filter(|pos| self.is_blocked(*pos)).map(|(r, c)| (r - piece.row, c - piece.col)).collect();
+        let mut boxed_in = false;
+        if let Some(dx) = blocked.iter().map(|(_, dc)| -dc.signum()).find(|dx| *dx != 0) {
+            piece.col += dx;
+            if !self.check_collision(&piece) { return piece; }
+            piece.col -= dx;
+            boxed_in = self.is_blocked((piece.row, piece.col - 1)) && self.is_blocked((piece.row, piece.col + 1));
+        }
+        if blocked.iter().any(|(dr, _)| *dr > 0) && self.floor_kicks < self.ruleset.floor_kick_limit {
+            piece.row -= 1;
+            if !self.check_collision(&piece) { self.floor_kicks += 1; return piece; }
+            piece.row += 1;
+        }
+        piece.rotation = old_rot;
+        if piece.shape != PieceShape::Pair || !boxed_in { return piece; }
+        match self.quick_turn_frame.take() {
+            Some(pressed) if self.frame - pressed <= self.ruleset.quick_turn_frames as u64 => {
+                piece.rotation = (old_rot + 2) % 4;
+                if self.check_collision(&piece) { piece.row -= 1; }
+                if self.check_collision(&piece) { piece.row += 1; piece.rotation = old_rot; }
             }
-            self.active_piece = Some(piece);
-            self.lock_piece();
+            _ => self.quick_turn_frame = Some(self.frame),
+        }
+        piece
+    }
+
+    pub fn hard_drop(&mut self) -> Option<ActivePuyo> {
+        let mut piece = self.active_piece.take()?;
+        loop {
+            piece.row += 1;
+            if self.check_collision(&piece) { piece.row -= 1; break; }
         }
+        self.active_piece = Some(piece);
+        self.lock_piece()
     }
 
     pub fn force_drop(&mut self) {
@@ -195,126 +456,315 @@ impl Board {
             if self.check_collision(&piece) {
                 piece.row -= 1; self.is_touching_ground = true;
             } else {
-                self.is_touching_ground = false; self.lock_timer = 0.0;
+                self.events.push(BoardEvent::PieceMoved { piece: piece.clone() });
+                self.is_touching_ground = false; self.lock_timer = 0;
             }
             self.active_piece = Some(piece);
         }
     }
 
-    pub fn update_logic(&mut self, delta_time: f32) -> bool {
-        let mut locked = false;
-        if let Some(mut piece) = self.active_piece.take() {
-            if piece.row > self.lowest_row_reached {
-                self.lowest_row_reached = piece.row; self.total_ground_timer = 0.0; self.ground_move_count = 0;
+    /// Moves the clock, margin time following. Mirrors take the frame each remote piece locked at.
+    pub fn set_frame(&mut self, frame: u64) {
+        self.frame = frame;
+        self.target_point = self.ruleset.target_point_at(frame);
+    }
+
+    pub fn level(&self) -> u32 { 1 + (self.frame / self.ruleset.level_frames.max(1) as u64) as u32 }
+
+    pub fn tick(&mut self, input: InputFrame) -> Option<ActivePuyo> {
+        if matches!(self.state, GameState::GameOver | GameState::Paused) { return None; }
+        self.set_frame(self.frame + 1);
+        match self.state {
+            GameState::Playing => self.tick_playing(input),
+            GameState::Fever => {
+                self.fever_timer = self.fever_timer.saturating_sub(1);
+                if self.fever_timer == 0 { self.end_fever(); return None; }
+                self.tick_playing(input)
             }
-            piece.row += 1;
-            let collision = self.check_collision(&piece);
-            piece.row -= 1;
-            if collision {
-                self.is_touching_ground = true;
-                self.lock_timer += delta_time;
-                self.total_ground_timer += delta_time;
-                if self.lock_timer > MAX_LOCK_TIME || self.total_ground_timer > MAX_TOTAL_GROUND_TIME {
-                    self.active_piece = Some(piece); self.lock_piece(); locked = true;
-                } else { self.active_piece = Some(piece); }
-            } else {
-                self.is_touching_ground = false; self.lock_timer = 0.0; self.active_piece = Some(piece);
+            GameState::ResolvingMatches | GameState::FeverResolving => {
+                self.resolve_timer += 1;
+                if self.resolve_timer >= self.ruleset.resolve_frames { self.resolve_timer = 0; self.resolve_step(); }
+                None
             }
+            _ => None,
         }
-        locked
     }
 
-    fn lock_piece(&mut self) {
-        if let Some(piece) = self.active_piece.take() {
-            for (r, c) in piece.get_positions().iter() {
-                if *r >= 0 && *r < self.height as i32 && *c >= 0 && *c < self.width as i32 {
-                    let puyo_type = if *r == piece.row && *c == piece.col { piece.axis_type } else { piece.sat_type };
-                    self.cells[*r as usize][*c as usize] = Some(puyo_type);
-                }
+    fn tick_playing(&mut self, input: InputFrame) -> Option<ActivePuyo> {
+        if input.rotate_cw { self.rotate_piece(1); }
+        if input.rotate_ccw { self.rotate_piece(3); }
+        if input.hard_drop { return self.hard_drop(); }
+
+        let (das_delay, das_speed) = (self.ruleset.das_delay_frames, self.ruleset.das_speed_frames.max(1));
+        for (dx, held) in [(-1, input.left), (1, input.right)] {
+            let timer = if dx < 0 { &mut self.das_left } else { &mut self.das_right };
+            if !held { *timer = 0; continue; }
+            *timer += 1;
+            let repeat = *timer > das_delay && (*timer - das_delay) % das_speed == 0;
+            if *timer == 1 || repeat { self.move_piece(dx); }
+        }
+
+        if input.soft_drop {
+            self.soft_drop_timer += 1;
+            if self.soft_drop_timer >= self.ruleset.soft_drop_frames { self.force_drop(); self.fall_timer = 0; self.soft_drop_timer = 0; }
+        } else { self.soft_drop_timer = 0; }
+
+        let mut piece = self.active_piece.take()?;
+        if piece.row > self.lowest_row_reached {
+            self.lowest_row_reached = piece.row; self.total_ground_timer = 0; self.ground_move_count = 0;
+        }
+        piece.row += 1;
+        let collision = self.check_collision(&piece);
+        piece.row -= 1;
+        self.active_piece = Some(piece);
+        if collision {
+            self.is_touching_ground = true;
+            self.lock_timer += 1;
+            self.total_ground_timer += 1;
+            if self.lock_timer > self.ruleset.lock_frames || self.total_ground_timer > self.ruleset.max_ground_frames {
+                return self.lock_piece();
             }
-            self.state = GameState::ResolvingMatches;
+        } else {
+            self.is_touching_ground = false; self.lock_timer = 0;
+            self.fall_timer += 1;
+            if self.fall_timer >= self.ruleset.gravity_interval(self.level()) { self.force_drop(); self.fall_timer = 0; }
         }
+        None
     }
 
-    pub fn apply_board_gravity(&mut self) -> bool {
-        let mut moved = false;
-        for col in 0..self.width {
-            for row in (0..self.height - 1).rev() {
-                if self.cells[row][col].is_some() && self.cells[row + 1][col].is_none() {
-                    let mut drop_row = row;
-                    while drop_row + 1 < self.height && self.cells[drop_row + 1][col].is_none() { drop_row += 1; }
-                    self.cells[drop_row][col] = self.cells[row][col].take();
-                    moved = true;
-                }
+    fn lock_piece(&mut self) -> Option<ActivePuyo> {
+        let piece = self.active_piece.take()?;
+        self.record_placement();
+        self.place_cells(&piece);
+        self.state = if self.in_fever() { GameState::FeverResolving } else { GameState::ResolvingMatches };
+        self.resolve_timer = 0;
+        self.events.push(BoardEvent::PieceLocked { piece: piece.clone(), checksum: self.spawn_checksum.take(), fever_timer: self.fever_timer, frame: self.frame });
+        Some(piece)
+    }
+
+    /// Cells landing in the ghost rows vanish.
+    fn place_cells(&mut self, piece: &ActivePuyo) {
+        for ((r, c), puyo_type) in piece.cells() {
+            if r >= self.ghost_rows() as i32 && r < self.height as i32 && c >= 0 && c < self.width as i32 {
+                self.cells[r as usize][c as usize] = Some(puyo_type);
             }
         }
-        moved
     }
 
-    pub fn check_matches(&mut self) -> bool {
-        let mut to_remove = HashSet::new();
-        let mut visited = HashSet::new();
-        let mut group_sizes = Vec::new();
-        let mut unique_colors = HashSet::new();
-        let mut total_puyos_cleared = 0;
-        for r in 0..self.height {
-            for c in 0..self.width {
-                if let Some(p_type) = self.cells[r][c] {
-                    if !visited.contains(&(r, c)) {
-                        let mut group = Vec::new();
-                        self.flood_fill(r, c, p_type, &mut group, &mut visited);
-                        if group.len() >= 4 {
-                            if group.iter().any(|(r, _)| *r >= VISIBLE_ROW_OFFSET) {
-                                unique_colors.insert(p_type);
-                                group_sizes.push(group.len() as u32);
-                                total_puyos_cleared += group.len() as u32;
-                                for pos in group { to_remove.insert(pos); }
-                            }
-                        }
-                    }
-                }
+    pub fn simulate_chain(&self) -> ChainReport { self.simulation_copy().resolve_chain() }
+
+    pub fn simulate_placement(&self, piece: &ActivePuyo) -> Option<ChainReport> { self.simulation_copy().apply_placement(piece) }
+
+    /// The board a chain plays out on, without the queue, undo history, chain history or pending events.
+    fn simulation_copy(&self) -> Board {
+        Board {
+            queue: PairQueue::Scripted(ScriptedQueue::new(Vec::new(), false)), preview: VecDeque::new(),
+            events: Vec::new(), history: None, chain_steps: Vec::new(), chain_history: VecDeque::new(),
+            ..self.clone()
+        }
+    }
+
+    pub fn apply_placement(&mut self, piece: &ActivePuyo) -> Option<ChainReport> {
+        let mut dropped = piece.clone();
+        if self.check_collision(&dropped) { return None; }
+        while !self.check_collision(&dropped) { dropped.row += 1; }
+        dropped.row -= 1;
+        self.place_cells(&dropped);
+        self.chain_count = 0;
+        let report = self.resolve_chain();
+        self.finish_chain();
+        self.settle_fever();
+        self.settle_puzzle();
+        Some(report)
+    }
+
+    fn resolve_chain(&mut self) -> ChainReport {
+        let start = self.events.len();
+        self.apply_board_gravity();
+        while self.check_matches() { self.apply_board_gravity(); }
+        self.update_all_clear();
+        let mut steps = Vec::new();
+        for event in self.events[start..].iter() {
+            match event {
+                BoardEvent::GroupsPopped { groups, .. } => steps.push(SimulatedStep { groups: groups.clone(), points: 0, garbage: 0 }),
+                BoardEvent::ChainStep { points, garbage, .. } => if let Some(step) = steps.last_mut() { step.points = *points; step.garbage = *garbage; },
+                _ => {}
             }
         }
-        if to_remove.is_empty() { return false; }
+        ChainReport {
+            score: steps.iter().map(|s| s.points).sum(), garbage: steps.iter().map(|s| s.garbage).sum(),
+            all_clear: !steps.is_empty() && self.is_empty(), steps, field: self.cells.clone(),
+        }
+    }
+
+    pub fn apply_board_gravity(&mut self) -> bool {
+        let mut field = Field::from_cells(&self.cells);
+        let moved = field.apply_gravity();
+        if moved { field.write_cells(&mut self.cells); }
+        moved
+    }
+
+    pub fn check_matches(&mut self) -> bool {
+        let mut field = Field::from_cells(&self.cells);
+        let visible = field.rows_mask(self.visible_top()..self.height);
+        let found = field.groups(self.ruleset.pop_count, visible);
+        if found.is_empty() { return false; }
         self.chain_count += 1;
-        self.calculate_score(unique_colors.len(), total_puyos_cleared, &group_sizes);
-        for (r, c) in to_remove { self.cells[r][c] = None; }
+        let popped = found.iter().fold(0, |m, (_, group)| m | group);
+        let adjacent = field.expand(popped) & visible;
+        let [garbage_hit, hard_hit, point_hit, sun_hit] = [PuyoType::Garbage, PuyoType::Hard, PuyoType::Point, PuyoType::Sun].map(|kind| adjacent & field.plane(kind));
+        let groups: Vec<PoppedGroup> = found.iter().map(|(color, group)| PoppedGroup { color: *color, positions: field.positions(*group) }).collect();
+        let unique_colors: HashSet<PuyoType> = groups.iter().map(|g| g.color).collect();
+        let group_sizes: Vec<u32> = groups.iter().map(|g| g.positions.len() as u32).collect();
+        let mut step = self.calculate_score(unique_colors.len(), group_sizes.iter().sum(), &group_sizes, point_hit.count_ones());
+        let (points, garbage) = (step.points, self.generate_garbage(step.points, sun_hit.count_ones() * self.ruleset.sun_garbage));
+        step.garbage = garbage;
+        self.chain_steps.push(step);
+        self.record_puzzle_pops(&groups);
+        let specials_cleared = [(PuyoType::Point, point_hit), (PuyoType::Sun, sun_hit)].into_iter().filter(|(_, mask)| *mask != 0)
+            .map(|(color, mask)| PoppedGroup { color, positions: field.positions(mask) }).collect();
+        field.clear(popped | garbage_hit | point_hit | sun_hit);
+        field.fill(hard_hit, PuyoType::Garbage);
+        field.write_cells(&mut self.cells);
+        self.events.push(BoardEvent::GroupsPopped { groups, garbage_cleared: field.positions(garbage_hit), specials_cleared });
+        self.events.push(BoardEvent::ChainStep { chain: self.chain_count, points, garbage });
         true
     }
 
-    fn calculate_score(&mut self, color_count_len: usize, total_cleared: u32, group_sizes: &[u32]) {
-        let chain_idx = (self.chain_count).min(19) as usize;
-        let cp = CHAIN_POWERS[chain_idx];
-        let cb = COLOR_BONUS[color_count_len.min(5) as usize];
+    fn calculate_score(&mut self, color_count_len: usize, total_cleared: u32, group_sizes: &[u32], point_cells: u32) -> ChainStepReport {
+        let cp = self.ruleset.chain_power(self.chain_count);
+        let cb = self.ruleset.color_bonus(color_count_len);
         let mut gb = 0;
-        for &size in group_sizes { gb += GROUP_BONUS[(size.saturating_sub(4)).min(7) as usize]; }
+        for &size in group_sizes { gb += self.ruleset.group_bonus(size as usize); }
         let mut multiplier = cp + cb + gb;
         if multiplier == 0 { multiplier = 1; }
         if multiplier > 999 { multiplier = 999; }
-        self.score += (10 * total_cleared) as i32 * multiplier as i32;
+        let bonus_points = point_cells * self.ruleset.point_score;
+        let points = 10 * total_cleared * multiplier + bonus_points;
+        self.score += points as i32;
+        ChainStepReport {
+            chain: self.chain_count, cleared: total_cleared, chain_power: cp, color_bonus: cb, group_bonus: gb, multiplier,
+            bonus_points, points, garbage: 0,
+        }
     }
 
-    fn flood_fill(&self, r: usize, c: usize, target_type: PuyoType, group: &mut Vec<(usize, usize)>, visited: &mut HashSet<(usize, usize)>) {
-        if visited.contains(&(r, c)) { return; }
-        visited.insert((r, c)); group.push((r, c));
-        for (dr, dc) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
-            let nr = r as i32 + dr; let nc = c as i32 + dc;
-            if nr >= 0 && nr < self.height as i32 && nc >= 0 && nc < self.width as i32 {
-                if let Some(cell_type) = self.cells[nr as usize][nc as usize] {
-                    if cell_type == target_type { self.flood_fill(nr as usize, nc as usize, target_type, group, visited); }
-                }
-            }
-        }
+    /// Moves the steps of the chain that just ended into the history.
+    fn finish_chain(&mut self) {
+        if self.chain_steps.is_empty() { return; }
+        if self.chain_history.len() == CHAIN_HISTORY_LEN { self.chain_history.pop_front(); }
+        self.chain_history.push_back(std::mem::take(&mut self.chain_steps));
+    }
+
+    fn generate_garbage(&mut self, points: u32, bonus: u32) -> u32 {
+        let total = (points as u64 * self.ruleset.attack_percent as u64 / 100) as u32 + self.leftover_points;
+        let target = self.target_point.max(1);
+        let mut garbage = total / target + bonus;
+        self.leftover_points = total % target;
+        if self.all_clear { garbage += self.ruleset.all_clear_bonus; self.all_clear = false; }
+        let offset = garbage.min(self.pending_garbage);
+        self.pending_garbage -= offset;
+        if offset > 0 && !self.in_fever() { self.fever_gauge = (self.fever_gauge + 1).min(self.ruleset.fever_gauge); }
+        garbage - offset
     }
 
     pub fn resolve_step(&mut self) {
         let fell = self.apply_board_gravity();
         if !fell {
             let matched = self.check_matches();
-            if !matched && self.state != GameState::GameOver { self.state = GameState::Playing; self.spawn_piece(); }
+            if !matched && self.state != GameState::GameOver {
+                self.update_all_clear();
+                if !self.garbage_dropped {
+                    self.garbage_dropped = true;
+                    self.finish_chain();
+                    self.settle_fever();
+                    if self.settle_puzzle() || self.drop_garbage() > 0 { return; }
+                }
+                self.state = if self.in_fever() { GameState::Fever } else { GameState::Playing };
+                self.spawn_piece();
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.cells.iter().all(|row| row.iter().all(Option::is_none)) }
+
+    pub fn update_all_clear(&mut self) {
+        if self.chain_count > 0 && !self.all_clear && self.is_empty() {
+            self.all_clear = true;
+            self.events.push(BoardEvent::AllClear);
         }
     }
 
+    pub fn queue_garbage(&mut self, amount: u32) { self.pending_garbage = self.pending_garbage.saturating_add(amount); }
+
+    pub fn drop_garbage(&mut self) -> u32 {
+        let amount = self.pending_garbage.min(self.ruleset.max_garbage_drop);
+        if amount == 0 || self.in_fever() { return 0; }
+        self.pending_garbage -= amount;
+        let mut per_col = vec![amount as usize / self.width; self.width];
+        let mut cols: Vec<usize> = (0..self.width).collect();
+        for i in 0..amount as usize % self.width {
+            let j = i + self.garbage_rng.index(self.width - i);
+            cols.swap(i, j); per_col[cols[i]] += 1;
+        }
+        for (col, count) in per_col.into_iter().enumerate() {
+            let ghost_rows = self.ghost_rows();
+            let mut row = (ghost_rows..self.height).rev().find(|&r| self.cells[r][col].is_none());
+            for _ in 0..count {
+                let Some(r) = row else { break };
+                self.cells[r][col] = Some(self.nuisance_kind());
+                row = r.checked_sub(1).filter(|r| *r >= ghost_rows);
+            }
+        }
+        self.events.push(BoardEvent::GarbageDropped { amount });
+        amount
+    }
+
+    pub fn in_fever(&self) -> bool { self.saved_cells.is_some() }
+
+    /// Runs once a turn has settled: a full gauge starts Fever, and during Fever any chain, fired
+    /// or botched, brings in a fresh pattern one step longer or shorter.
+    fn settle_fever(&mut self) {
+        if self.in_fever() {
+            if self.chain_count == 0 { return; }
+            self.fever_chain = if self.chain_count >= self.fever_chain { self.fever_chain + 1 } else { self.fever_chain.saturating_sub(1) };
+            self.fever_chain = self.fever_chain.clamp(FEVER_MIN_CHAIN, FEVER_MAX_CHAIN);
+            self.load_fever_pattern();
+        } else if self.ruleset.fever_gauge > 0 && self.fever_gauge >= self.ruleset.fever_gauge {
+            self.fever_gauge = 0;
+            self.fever_timer = self.ruleset.fever_frames;
+            self.saved_cells = Some(std::mem::take(&mut self.cells));
+            self.load_fever_pattern();
+            self.state = GameState::Fever;
+            self.events.push(BoardEvent::FeverStarted { chain: self.fever_chain });
+        }
+    }
+
+    fn load_fever_pattern(&mut self) {
+        let patterns = FeverPattern::for_chain(self.fever_chain);
+        let pattern = patterns[self.garbage_rng.index(patterns.len())];
+        let mut colors = self.colors.clone();
+        self.garbage_rng.shuffle(&mut colors);
+        self.cells = pattern.cells(self.width, self.height, &colors);
+    }
+
+    /// Restores the normal field, the piece in hand starting over from the top.
+    pub fn end_fever(&mut self) {
+        let Some(cells) = self.saved_cells.take() else { return };
+        self.cells = cells;
+        self.state = GameState::Playing;
+        self.events.push(BoardEvent::FeverEnded);
+        if let Some(piece) = self.active_piece.take() {
+            let (row, col) = self.spawn_position();
+            self.start_piece(ActivePuyo { row, col, rotation: 0, ..piece });
+        }
+    }
+
+    /// Dropped nuisance turns into one of the ruleset's special cells `special_chance` percent of the time.
+    fn nuisance_kind(&mut self) -> PuyoType {
+        if self.ruleset.special_cells.is_empty() || self.garbage_rng.below(100) >= self.ruleset.special_chance as u64 { return PuyoType::Garbage; }
+        self.ruleset.special_cells[self.garbage_rng.index(self.ruleset.special_cells.len())]
+    }
+
     pub fn toggle_pause(&mut self) {
         match self.state {
             GameState::Paused => {
@@ -328,3 +778,250 @@ impl Board {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(ruleset: Ruleset, text: &str) -> Board {
+        Board::from_notation(ruleset, PuyoType::COLORS[..4].to_vec(), 1, text).unwrap()
+    }
+
+    /// Pops whatever `text` holds as the first step of a new chain and returns the nuisance it sent.
+    fn pop(board: &mut Board, text: &str) -> u32 {
+        board.set_notation(text).unwrap();
+        board.chain_count = 0;
+        assert!(board.check_matches());
+        board.chain_steps.last().unwrap().garbage
+    }
+
+    fn piece(shape: PieceShape, row: i32, col: i32, rotation: usize) -> ActivePuyo {
+        ActivePuyo { row, col, rotation, axis_type: PuyoType::Red, sat_type: PuyoType::Blue, shape }
+    }
+
+    type Offsets = Vec<((i32, i32), PuyoType)>;
+
+    /// Cells of `piece` relative to its axis, sorted.
+    fn offsets(piece: &ActivePuyo) -> Offsets {
+        let mut cells: Vec<_> = piece.cells().into_iter().map(|((r, c), kind)| ((r - piece.row, c - piece.col), kind)).collect();
+        cells.sort_by_key(|(pos, _)| *pos);
+        cells
+    }
+
+    #[test]
+    fn shape_cells_at_each_rotation() {
+        use PuyoType::{Blue as B, Red as R};
+        let expected: [(PieceShape, [&[_]; 4]); 4] = [
+            (PieceShape::Pair, [&[((-1, 0), B), ((0, 0), R)], &[((0, 0), R), ((0, 1), B)], &[((0, 0), R), ((1, 0), B)], &[((0, -1), B), ((0, 0), R)]]),
+            (PieceShape::TripleVertical, [
+                &[((-1, 0), R), ((0, 0), R), ((0, 1), B)], &[((0, 0), R), ((0, 1), R), ((1, 0), B)],
+                &[((0, -1), B), ((0, 0), R), ((1, 0), R)], &[((-1, 0), B), ((0, -1), R), ((0, 0), R)],
+            ]),
+            (PieceShape::TripleHorizontal, [
+                &[((-1, 0), B), ((0, 0), R), ((0, 1), R)], &[((0, 0), R), ((0, 1), B), ((1, 0), R)],
+                &[((0, -1), R), ((0, 0), R), ((1, 0), B)], &[((-1, 0), R), ((0, -1), B), ((0, 0), R)],
+            ]),
+            (PieceShape::Quad, [
+                &[((-1, 0), R), ((-1, 1), B), ((0, 0), R), ((0, 1), B)], &[((-1, 0), R), ((-1, 1), R), ((0, 0), B), ((0, 1), B)],
+                &[((-1, 0), B), ((-1, 1), R), ((0, 0), B), ((0, 1), R)], &[((-1, 0), B), ((-1, 1), B), ((0, 0), R), ((0, 1), R)],
+            ]),
+        ];
+        for (shape, rotations) in expected {
+            for (rotation, cells) in rotations.iter().enumerate() {
+                assert_eq!(offsets(&piece(shape, 5, 2, rotation)), cells.to_vec() as Offsets, "{:?} rotation {}", shape, rotation);
+            }
+        }
+        for rotation in 0..4 {
+            assert_eq!(offsets(&piece(PieceShape::BigQuad, 5, 2, rotation)), [((-1, 0), R), ((-1, 1), R), ((0, 0), R), ((0, 1), R)]);
+        }
+    }
+
+    #[test]
+    fn big_quad_cycles_through_the_palette() {
+        let mut board = board(Ruleset::tsu(), "");
+        board.active_piece = Some(piece(PieceShape::BigQuad, 6, 2, 0));
+        let color = |board: &Board| board.active_piece.as_ref().unwrap().cells().iter().map(|(_, kind)| *kind).collect::<HashSet<_>>();
+        board.rotate_piece(1);
+        assert_eq!(color(&board), HashSet::from([PuyoType::Blue]));
+        board.rotate_piece(3);
+        board.rotate_piece(3);
+        assert_eq!(color(&board), HashSet::from([PuyoType::Green]), "counter-clockwise wraps to the last color");
+        board.rotate_piece(1);
+        assert_eq!(color(&board), HashSet::from([PuyoType::Red]));
+    }
+
+    #[test]
+    fn quad_rotates_its_colors_in_place() {
+        let mut board = board(Ruleset::tsu(), "");
+        let start = piece(PieceShape::Quad, 6, 0, 0);
+        board.active_piece = Some(start.clone());
+        board.rotate_piece(1);
+        let rotated = board.active_piece.clone().unwrap();
+        assert_eq!((rotated.row, rotated.col, rotated.rotation), (6, 0, 1));
+        assert_eq!(rotated.get_positions(), start.get_positions());
+        assert_eq!(offsets(&rotated), offsets(&piece(PieceShape::Quad, 6, 0, 1)));
+    }
+
+    #[test]
+    fn triples_kick_off_walls() {
+        let mut board = board(Ruleset::tsu(), "");
+        board.active_piece = Some(piece(PieceShape::TripleVertical, 6, 5, 3));
+        board.rotate_piece(1);
+        assert_eq!(board.active_piece, Some(piece(PieceShape::TripleVertical, 6, 4, 0)));
+        board.active_piece = Some(piece(PieceShape::TripleHorizontal, 6, 0, 0));
+        board.rotate_piece(3);
+        assert_eq!(board.active_piece, Some(piece(PieceShape::TripleHorizontal, 6, 1, 3)));
+    }
+
+    #[test]
+    fn locking_writes_every_cell() {
+        for (shape, count) in [(PieceShape::TripleVertical, 3), (PieceShape::TripleHorizontal, 3), (PieceShape::Quad, 4), (PieceShape::BigQuad, 4)] {
+            let mut board = board(Ruleset::tsu(), "");
+            board.active_piece = Some(piece(shape, 6, 2, 1));
+            let locked = board.hard_drop().unwrap();
+            assert_eq!(board.cells.iter().flatten().flatten().count(), count, "{:?}", shape);
+            for ((r, c), kind) in locked.cells() { assert_eq!(board.cells[r as usize][c as usize], Some(kind)); }
+        }
+    }
+
+    #[test]
+    fn special_cells_next_to_a_pop() {
+        let mut board = board(Ruleset::tsu(), "H*S...\nRRRR..");
+        assert!(board.check_matches());
+        let step = board.chain_steps.last().unwrap();
+        assert_eq!((step.bonus_points, step.points, step.garbage), (100, 140, 2 + 3));
+        board.apply_board_gravity();
+        assert_eq!(board.to_notation(), "O.....");
+    }
+
+    #[test]
+    fn hard_puyos_take_two_pops() {
+        let mut board = board(Ruleset::tsu(), "H.....\nRRRR..");
+        assert!(board.check_matches());
+        board.apply_board_gravity();
+        assert_eq!(board.to_notation(), "O.....");
+        pop(&mut board, "OBBBB.");
+        assert!(board.is_empty(), "the nuisance it left clears on the next pop");
+    }
+
+    #[test]
+    fn nuisance_turns_only_into_listed_specials() {
+        let mut board = board(Ruleset { special_cells: vec![PuyoType::Sun, PuyoType::Hard], special_chance: 50, ..Ruleset::tsu() }, "");
+        let kinds: HashSet<PuyoType> = (0..1000).map(|_| board.nuisance_kind()).collect();
+        assert_eq!(kinds, HashSet::from([PuyoType::Garbage, PuyoType::Sun, PuyoType::Hard]));
+        let mut board = self::board(Ruleset { special_cells: vec![PuyoType::Point], special_chance: 100, ..Ruleset::tsu() }, "");
+        board.queue_garbage(12);
+        assert_eq!(board.drop_garbage(), 12);
+        assert!(board.cells.iter().flatten().flatten().all(|kind| *kind == PuyoType::Point));
+    }
+
+    /// A pair standing in a one-wide well four rows deep, its axis `above_floor` rows up.
+    fn well(ruleset: Ruleset, above_floor: i32) -> Board {
+        let mut board = board(ruleset, "OO.OOO\nOO.OOO\nOO.OOO\nOO.OOO");
+        board.active_piece = Some(piece(PieceShape::Pair, board.height as i32 - 1 - above_floor, 2, 0));
+        board
+    }
+
+    fn rotation(board: &Board) -> (i32, usize) { board.active_piece.as_ref().map(|p| (p.row, p.rotation)).unwrap() }
+
+    #[test]
+    fn quick_turn_needs_a_second_press_in_time() {
+        let mut board = well(Ruleset::tsu(), 0);
+        let bottom = board.height as i32 - 1;
+        board.rotate_piece(1);
+        assert_eq!(rotation(&board), (bottom, 0), "the first press only arms the quick turn");
+        board.rotate_piece(1);
+        assert_eq!(rotation(&board), (bottom - 1, 2), "pushed up off the floor");
+
+        let mut board = well(Ruleset::tsu(), 0);
+        board.rotate_piece(1);
+        board.set_frame(board.ruleset.quick_turn_frames as u64 + 1);
+        board.rotate_piece(1);
+        assert_eq!(rotation(&board), (bottom, 0), "too late, this press arms it again");
+        board.rotate_piece(3);
+        assert_eq!(rotation(&board), (bottom - 1, 2));
+    }
+
+    #[test]
+    fn floor_kicks_run_out_without_flipping() {
+        let mut board = board(Ruleset::tsu(), "");
+        let bottom = board.height as i32 - 1;
+        board.active_piece = Some(piece(PieceShape::Pair, bottom, 2, 1));
+        board.floor_kicks = board.ruleset.floor_kick_limit - 1;
+        board.rotate_piece(1);
+        assert_eq!((rotation(&board), board.floor_kicks), ((bottom - 1, 2), board.ruleset.floor_kick_limit));
+        board.active_piece = Some(piece(PieceShape::Pair, bottom, 2, 1));
+        for _ in 0..2 {
+            board.rotate_piece(1);
+            assert_eq!(rotation(&board), (bottom, 1), "a pair lying on the floor is not boxed in");
+        }
+    }
+
+    #[test]
+    fn legacy_quick_turns_at_once() {
+        let mut board = well(Ruleset::classic(), 1);
+        let row = board.height as i32 - 2;
+        board.rotate_piece(1);
+        assert_eq!(rotation(&board), (row, 2));
+    }
+
+    #[test]
+    fn simulations_leave_the_board_alone() {
+        let mut board = board(Ruleset::tsu(), "Y.....\nRYYY..\nRRR...");
+        board.enable_history(4);
+        board.spawn_piece();
+        board.queue_garbage(5);
+        let before = serde_json::to_string(&board).unwrap();
+        let report = board.simulate_chain();
+        assert_eq!((report.chain_length(), report.score), (2, 40 + 320));
+        let piece = ActivePuyo { row: board.visible_top() as i32, col: 5, rotation: 0, axis_type: PuyoType::Red, sat_type: PuyoType::Red, shape: PieceShape::Pair };
+        assert_eq!(board.simulate_placement(&piece).unwrap().chain_length(), 2);
+        assert_eq!(serde_json::to_string(&board).unwrap(), before);
+        assert!(board.chain_steps.is_empty() && board.chain_history.is_empty());
+        assert_eq!(board.drain_events().len(), 1, "only the spawn is pending");
+        board.hard_drop();
+        assert!(board.can_undo());
+    }
+
+    #[test]
+    fn leftover_points_carry_to_the_next_chain() {
+        let mut board = board(Ruleset::tsu(), "");
+        assert_eq!(board.generate_garbage(100, 0), 1);
+        assert_eq!(board.leftover_points, 30);
+        assert_eq!(board.generate_garbage(40, 0), 1);
+        assert_eq!(board.leftover_points, 0);
+        assert_eq!(board.generate_garbage(69, 2), 2, "sun bonus is added as is");
+        assert_eq!(board.leftover_points, 69);
+    }
+
+    #[test]
+    fn incoming_nuisance_is_offset_first() {
+        let mut board = board(Ruleset { target_point: 10, ..Ruleset::tsu() }, "");
+        board.queue_garbage(3);
+        assert_eq!(pop(&mut board, "RRRR.."), 1);
+        assert_eq!(board.pending_garbage, 0);
+        assert!(board.drain_events().contains(&BoardEvent::ChainStep { chain: 1, points: 40, garbage: 1 }));
+        board.queue_garbage(9);
+        assert_eq!(pop(&mut board, "BBBB.."), 0);
+        assert_eq!(board.pending_garbage, 5);
+    }
+
+    #[test]
+    fn pending_garbage_saturates() {
+        let mut board = board(Ruleset::tsu(), "");
+        board.queue_garbage(u32::MAX);
+        board.queue_garbage(1);
+        assert_eq!(board.pending_garbage, u32::MAX);
+    }
+
+    #[test]
+    fn all_clear_bonus_goes_to_the_next_attack_once() {
+        let mut board = board(Ruleset::tsu(), "");
+        assert_eq!(pop(&mut board, "RRRR.."), 0);
+        board.update_all_clear();
+        assert!(board.all_clear);
+        assert_eq!(pop(&mut board, "BBBB.."), 30 + 1, "bonus plus the 80 points of both pops");
+        assert!(!board.all_clear);
+        assert_eq!(pop(&mut board, "YYYY.."), 0);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{Board, PuyoType, Ruleset};
+use std::fmt;
+
+/// Digits of the puyop-style field code, two cells per character.
+const CODE_ALPHABET: &[u8; 64] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ[]";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+    UnknownCell { line: usize, ch: char },
+    WrongWidth { line: usize, expected: usize, found: usize },
+    TooTall { rows: usize, max: usize },
+    UnknownCode(char),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::UnknownCell { line, ch } => write!(f, "unknown cell '{}' on line {}", ch, line + 1),
+            NotationError::WrongWidth { line, expected, found } => write!(f, "line {} has {} cells, expected {}", line + 1, found, expected),
+            NotationError::TooTall { rows, max } => write!(f, "{} rows do not fit in {}", rows, max),
+            NotationError::UnknownCode(ch) => write!(f, "unknown field code character '{}'", ch),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+fn cell_char(cell: Option<PuyoType>) -> char {
+    match cell {
+        None => '.',
+        Some(PuyoType::Red) => 'R', Some(PuyoType::Blue) => 'B', Some(PuyoType::Yellow) => 'Y',
+        Some(PuyoType::Green) => 'G', Some(PuyoType::Purple) => 'P', Some(PuyoType::Garbage) => 'O',
+        Some(PuyoType::Hard) => 'H', Some(PuyoType::Point) => '*', Some(PuyoType::Sun) => 'S',
+    }
+}
+
+fn parse_cell(ch: char) -> Option<Option<PuyoType>> {
+    Some(Some(match ch.to_ascii_uppercase() {
+        '.' => return Some(None),
+        'R' => PuyoType::Red, 'B' => PuyoType::Blue, 'Y' => PuyoType::Yellow,
+        'G' => PuyoType::Green, 'P' => PuyoType::Purple, 'O' => PuyoType::Garbage,
+        'H' => PuyoType::Hard, '*' => PuyoType::Point, 'S' => PuyoType::Sun,
+        _ => return None,
+    }))
+}
+
+/// puyop numbering, special cells have no digit and are written as nuisance.
+fn cell_code(cell: Option<PuyoType>) -> u8 {
+    match cell {
+        None => 0,
+        Some(PuyoType::Red) => 1, Some(PuyoType::Green) => 2, Some(PuyoType::Blue) => 3,
+        Some(PuyoType::Yellow) => 4, Some(PuyoType::Purple) => 5, Some(_) => 6,
+    }
+}
+
+fn parse_code(code: u8) -> Option<PuyoType> {
+    match code {
+        1 => Some(PuyoType::Red), 2 => Some(PuyoType::Green), 3 => Some(PuyoType::Blue),
+        4 => Some(PuyoType::Yellow), 5 => Some(PuyoType::Purple), 6 => Some(PuyoType::Garbage),
+        _ => None,
+    }
+}
+
+impl Board {
+    /// Board from rows like `..RB..`, see `set_notation`.
+    pub fn from_notation(ruleset: Ruleset, colors: Vec<PuyoType>, seed: u64, text: &str) -> Result<Board, NotationError> {
+        let mut board = Board::new(ruleset, colors, seed);
+        board.set_notation(text)?;
+        Ok(board)
+    }
+
+    /// Rows from the highest filled one down to the floor, `.` for empty, `RBYGP` for colors, `O` for
+    /// nuisance, `H` hard, `*` point and `S` sun puyos.
+    pub fn to_notation(&self) -> String {
+        let top = self.cells.iter().position(|row| row.iter().any(Option::is_some)).unwrap_or(self.height);
+        self.cells[top..].iter().map(|row| row.iter().map(|cell| cell_char(*cell)).collect::<String>()).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Replaces the field with text rows sitting on the floor, blank lines and surrounding spaces ignored.
+    pub fn set_notation(&mut self, text: &str) -> Result<(), NotationError> {
+        let lines: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        let max = self.height - self.ghost_rows();
+        if lines.len() > max { return Err(NotationError::TooTall { rows: lines.len(), max }); }
+        let mut cells = vec![vec![None; self.width]; self.height];
+        for (i, line) in lines.iter().enumerate() {
+            let row: Vec<Option<PuyoType>> = line.chars().map(|ch| parse_cell(ch).ok_or(NotationError::UnknownCell { line: i, ch })).collect::<Result<_, _>>()?;
+            if row.len() != self.width { return Err(NotationError::WrongWidth { line: i, expected: self.width, found: row.len() }); }
+            cells[self.height - lines.len() + i] = row;
+        }
+        self.cells = cells;
+        Ok(())
+    }
+
+    /// Compact URL-safe code of the hidden and visible rows in the puyop.com `/s/` layout: cells
+    /// read left to right from the top, numbered empty 0, red 1, green 2, blue 3, yellow 4,
+    /// purple 5 and nuisance 6, packed two per character with leading empty pairs dropped.
+    pub fn to_field_code(&self) -> String {
+        let mut codes: Vec<u8> = self.cells[self.ghost_rows()..].iter().flatten().map(|cell| cell_code(*cell)).collect();
+        if codes.len() % 2 == 1 { codes.insert(0, 0); }
+        let code: String = codes.chunks(2).map(|pair| CODE_ALPHABET[(pair[0] * 8 + pair[1]) as usize] as char).collect();
+        code.trim_start_matches('0').to_string()
+    }
+
+    pub fn set_field_code(&mut self, code: &str) -> Result<(), NotationError> {
+        let mut codes = Vec::with_capacity(code.len() * 2);
+        for ch in code.chars() {
+            let digit = CODE_ALPHABET.iter().position(|c| *c as char == ch).ok_or(NotationError::UnknownCode(ch))? as u8;
+            codes.extend([digit / 8, digit % 8]);
+        }
+        let ghost_rows = self.ghost_rows();
+        let total = (self.height - ghost_rows) * self.width;
+        let extra = codes.len().saturating_sub(total);
+        if codes[..extra].iter().any(|c| *c != 0) { return Err(NotationError::TooTall { rows: codes.len().div_ceil(self.width), max: self.height - ghost_rows }); }
+        let codes = &codes[extra..];
+        let mut cells = vec![vec![None; self.width]; self.height];
+        for (i, code) in codes.iter().enumerate() {
+            let idx = total - codes.len() + i;
+            cells[ghost_rows + idx / self.width][idx % self.width] = parse_code(*code);
+        }
+        self.cells = cells;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(text: &str) -> Board {
+        Board::from_notation(Ruleset::tsu(), PuyoType::COLORS.to_vec(), 1, text).unwrap()
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let text = "..RB..\nOHGY*S\nRRBBYP";
+        let board = board(text);
+        assert_eq!(board.cells[board.height - 1][5], Some(PuyoType::Purple));
+        assert_eq!(board.cells[board.height - 2][1], Some(PuyoType::Hard));
+        assert_eq!(board.to_notation(), text);
+    }
+
+    #[test]
+    fn field_code_round_trip() {
+        let board = board("..RB..\nOOGY..\nRRBBYP");
+        let code = board.to_field_code();
+        assert_eq!(code, "b0Sk09rB");
+        let mut copy = Board::new(Ruleset::tsu(), PuyoType::COLORS.to_vec(), 2);
+        copy.set_field_code(&code).unwrap();
+        assert_eq!(copy.cells, board.cells);
+    }
+
+    #[test]
+    fn rejects_bad_rows() {
+        let mut board = board("");
+        assert_eq!(board.set_notation("..RX.."), Err(NotationError::UnknownCell { line: 0, ch: 'X' }));
+        assert_eq!(board.set_notation("RB"), Err(NotationError::WrongWidth { line: 0, expected: 6, found: 2 }));
+        assert_eq!(board.set_field_code("-"), Err(NotationError::UnknownCode('-')));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{Board, BoardEvent, NotationError, Pair, PairQueue, PoppedGroup, PuyoType, Ruleset, ScriptedQueue};
+use serde::{Deserialize, Serialize};
+
+/// Goal of a puzzle, checked each time a turn settles.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Objective {
+    AllClear,
+    Chain(u32),
+    ClearColor { color: PuyoType, count: u32 },
+    SimultaneousGroups(u32),
+}
+
+impl Objective {
+    pub fn is_met(&self, progress: &PuzzleProgress) -> bool {
+        match self {
+            Objective::AllClear => progress.all_clear,
+            Objective::Chain(n) => progress.best_chain >= *n,
+            Objective::ClearColor { color, count } => progress.cleared[color.to_u8() as usize] >= *count,
+            Objective::SimultaneousGroups(n) => progress.best_groups >= *n,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Objective::AllClear => "Clear the whole field".to_string(),
+            Objective::Chain(n) => format!("Make a {}-chain", n),
+            Objective::ClearColor { color, count } => format!("Clear {} {:?}", count, color),
+            Objective::SimultaneousGroups(n) => format!("Pop {} groups at once", n),
+        }
+    }
+}
+
+/// Nazo Puyo: a starting field in text notation, a fixed pair sequence and an objective.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub name: String,
+    pub field: String,
+    pub pairs: Vec<Pair>,
+    pub objective: Objective,
+    #[serde(default)] pub ruleset: Ruleset,
+}
+
+impl Puzzle {
+    /// Board loaded with the field and pairs, the first piece still to be spawned.
+    pub fn board(&self) -> Result<Board, NotationError> {
+        let mut colors: Vec<PuyoType> = Vec::new();
+        for color in self.pairs.iter().flat_map(|(a, b)| [*a, *b]) {
+            if !colors.contains(&color) { colors.push(color); }
+        }
+        let queue = PairQueue::Scripted(ScriptedQueue::new(self.pairs.clone(), false));
+        let mut board = Board::with_queue(self.ruleset.clone(), colors, queue, 0);
+        board.set_notation(&self.field)?;
+        board.puzzle = Some(PuzzleProgress::new(self.objective.clone()));
+        Ok(board)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PuzzleProgress {
+    pub objective: Objective,
+    pub best_chain: u32, pub best_groups: u32, pub all_clear: bool,
+    /// Puyos popped so far, indexed by `PuyoType::to_u8`.
+    pub cleared: [u32; PuyoType::ALL.len()],
+    /// Set once the puzzle is solved or failed.
+    pub solved: Option<bool>,
+}
+
+impl PuzzleProgress {
+    pub fn new(objective: Objective) -> PuzzleProgress {
+        PuzzleProgress { objective, best_chain: 0, best_groups: 0, all_clear: false, cleared: [0; PuyoType::ALL.len()], solved: None }
+    }
+}
+
+impl Board {
+    pub(crate) fn record_puzzle_pops(&mut self, groups: &[PoppedGroup]) {
+        let Some(progress) = self.puzzle.as_mut() else { return };
+        progress.best_chain = progress.best_chain.max(self.chain_count);
+        progress.best_groups = progress.best_groups.max(groups.len() as u32);
+        for group in groups { progress.cleared[group.color.to_u8() as usize] += group.positions.len() as u32; }
+    }
+
+    /// Ends the board once the objective is met, returning whether the puzzle is over.
+    pub(crate) fn settle_puzzle(&mut self) -> bool {
+        let all_clear = self.chain_count > 0 && self.is_empty();
+        let Some(progress) = self.puzzle.as_mut() else { return false };
+        if progress.solved.is_some() { return true; }
+        progress.all_clear |= all_clear;
+        if !progress.objective.is_met(progress) { return false; }
+        progress.solved = Some(true);
+        self.events.push(BoardEvent::PuzzleFinished { solved: true });
+        self.set_game_over();
+        true
+    }
+
+    /// Running out of pairs or topping out before the objective is met fails the puzzle.
+    pub(crate) fn fail_puzzle(&mut self) {
+        let Some(progress) = self.puzzle.as_mut().filter(|p| p.solved.is_none()) else { return };
+        progress.solved = Some(false);
+        self.events.push(BoardEvent::PuzzleFinished { solved: false });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActivePuyo, PieceShape};
+
+    fn puzzle(field: &str, pairs: Vec<Pair>, objective: Objective) -> Board {
+        Puzzle { name: "test".to_string(), field: field.to_string(), pairs, objective, ruleset: Ruleset::tsu() }.board().unwrap()
+    }
+
+    fn place(board: &mut Board, col: i32, rotation: usize) {
+        let (axis_type, sat_type) = board.preview.pop_front().unwrap();
+        let row = board.visible_top() as i32;
+        board.apply_placement(&ActivePuyo { row, col, rotation, axis_type, sat_type, shape: PieceShape::Pair });
+    }
+
+    #[test]
+    fn chain_objective_solves() {
+        let field = "R.....\nR.....\nYR....\nYY....";
+        let mut board = puzzle(field, vec![(PuyoType::Red, PuyoType::Yellow)], Objective::Chain(2));
+        place(&mut board, 2, 0);
+        assert_eq!(board.puzzle.as_ref().unwrap().solved, None);
+        let mut board = puzzle(field, vec![(PuyoType::Yellow, PuyoType::Red)], Objective::Chain(2));
+        place(&mut board, 2, 0);
+        assert_eq!(board.puzzle.as_ref().unwrap().solved, Some(true));
+    }
+
+    #[test]
+    fn running_out_of_pairs_fails() {
+        let mut board = puzzle("RR....", vec![(PuyoType::Blue, PuyoType::Blue)], Objective::ClearColor { color: PuyoType::Red, count: 4 });
+        board.spawn_piece();
+        board.hard_drop();
+        while board.state != crate::GameState::GameOver { board.tick(Default::default()); }
+        assert_eq!(board.puzzle.as_ref().unwrap().solved, Some(false));
+        assert!(board.drain_events().contains(&BoardEvent::PuzzleFinished { solved: false }));
+    }
+}
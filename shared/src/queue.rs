@@ -0,0 +1,176 @@
+use crate::{PuyoRng, PuyoType};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// `(axis, satellite)` colors of a pair.
+pub type Pair = (PuyoType, PuyoType);
+
+pub trait PieceQueue {
+    /// Deals the next pair, `None` once a finite sequence is exhausted.
+    fn next_pair(&mut self) -> Option<Pair>;
+    /// Number of pairs dealt so far.
+    fn position(&self) -> usize;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum QueueKind { Tsu, Random }
+
+/// Generated queues need at least one color to deal from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NoColors;
+
+impl fmt::Display for NoColors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "queue has no colors to deal") }
+}
+
+impl std::error::Error for NoColors {}
+
+pub const TSU_TABLE_PAIRS: usize = 128;
+const TSU_RESTRICTED_PAIRS: usize = 2;
+const TSU_RESTRICTED_COLORS: usize = 3;
+
+/// Tsu-style table: 128 pairs pre-generated from the seed with every color equally represented,
+/// the first two pairs only using three colors, cycling once exhausted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "TsuQueueState", into = "TsuQueueState")]
+pub struct TsuQueue { seed: u64, colors: Vec<PuyoType>, index: usize, table: Vec<Pair> }
+
+#[derive(Serialize, Deserialize)]
+struct TsuQueueState { seed: u64, colors: Vec<PuyoType>, index: usize }
+
+impl TsuQueue {
+    pub fn new(colors: &[PuyoType], seed: u64) -> Result<TsuQueue, NoColors> {
+        if colors.is_empty() { return Err(NoColors); }
+        let mut puyos: Vec<PuyoType> = (0..TSU_TABLE_PAIRS * 2).map(|i| colors[i % colors.len()]).collect();
+        PuyoRng::new(seed).shuffle(&mut puyos);
+        let allowed = &colors[..colors.len().min(TSU_RESTRICTED_COLORS)];
+        for i in 0..TSU_RESTRICTED_PAIRS * 2 {
+            if allowed.contains(&puyos[i]) { continue; }
+            if let Some(j) = (TSU_RESTRICTED_PAIRS * 2..puyos.len()).find(|&j| allowed.contains(&puyos[j])) { puyos.swap(i, j); }
+        }
+        let table = puyos.chunks(2).map(|p| (p[0], p[1])).collect();
+        Ok(TsuQueue { seed, colors: colors.to_vec(), index: 0, table })
+    }
+}
+
+impl PieceQueue for TsuQueue {
+    fn next_pair(&mut self) -> Option<Pair> {
+        let pair = self.table[self.index % self.table.len()];
+        self.index += 1;
+        Some(pair)
+    }
+    fn position(&self) -> usize { self.index }
+}
+
+impl TryFrom<TsuQueueState> for TsuQueue {
+    type Error = NoColors;
+    fn try_from(state: TsuQueueState) -> Result<TsuQueue, NoColors> { Ok(TsuQueue { index: state.index, ..TsuQueue::new(&state.colors, state.seed)? }) }
+}
+
+impl From<TsuQueue> for TsuQueueState {
+    fn from(queue: TsuQueue) -> TsuQueueState { TsuQueueState { seed: queue.seed, colors: queue.colors, index: queue.index } }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "RandomQueueState")]
+pub struct RandomQueue { rng: PuyoRng, colors: Vec<PuyoType>, index: usize }
+
+#[derive(Deserialize)]
+struct RandomQueueState { rng: PuyoRng, colors: Vec<PuyoType>, index: usize }
+
+impl RandomQueue {
+    pub fn new(colors: &[PuyoType], seed: u64) -> Result<RandomQueue, NoColors> {
+        if colors.is_empty() { return Err(NoColors); }
+        Ok(RandomQueue { rng: PuyoRng::new(seed), colors: colors.to_vec(), index: 0 })
+    }
+}
+
+impl TryFrom<RandomQueueState> for RandomQueue {
+    type Error = NoColors;
+    fn try_from(state: RandomQueueState) -> Result<RandomQueue, NoColors> {
+        if state.colors.is_empty() { return Err(NoColors); }
+        Ok(RandomQueue { rng: state.rng, colors: state.colors, index: state.index })
+    }
+}
+
+impl PieceQueue for RandomQueue {
+    fn next_pair(&mut self) -> Option<Pair> {
+        self.index += 1;
+        Some((PuyoType::random_from(&mut self.rng, &self.colors), PuyoType::random_from(&mut self.rng, &self.colors)))
+    }
+    fn position(&self) -> usize { self.index }
+}
+
+/// Fixed sequence for puzzles and tests, optionally looping.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScriptedQueue { pairs: Vec<Pair>, index: usize, looping: bool }
+
+impl ScriptedQueue {
+    pub fn new(pairs: Vec<Pair>, looping: bool) -> ScriptedQueue { ScriptedQueue { pairs, index: 0, looping } }
+    pub fn remaining(&self) -> usize { if self.looping { usize::MAX } else { self.pairs.len().saturating_sub(self.index) } }
+}
+
+impl PieceQueue for ScriptedQueue {
+    fn next_pair(&mut self) -> Option<Pair> {
+        if self.pairs.is_empty() || (!self.looping && self.index >= self.pairs.len()) { return None; }
+        let pair = self.pairs[self.index % self.pairs.len()];
+        self.index += 1;
+        Some(pair)
+    }
+    fn position(&self) -> usize { self.index }
+}
+
+/// Serializable queue owned by a `Board`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PairQueue { Tsu(TsuQueue), Random(RandomQueue), Scripted(ScriptedQueue) }
+
+impl PairQueue {
+    pub fn new(kind: QueueKind, colors: &[PuyoType], seed: u64) -> Result<PairQueue, NoColors> {
+        Ok(match kind {
+            QueueKind::Tsu => PairQueue::Tsu(TsuQueue::new(colors, seed)?),
+            QueueKind::Random => PairQueue::Random(RandomQueue::new(colors, seed)?),
+        })
+    }
+
+    /// Kind and colors of a generated queue, `None` for scripted ones.
+    pub fn generator(&self) -> Option<(QueueKind, &[PuyoType])> {
+        match self {
+            PairQueue::Tsu(q) => Some((QueueKind::Tsu, &q.colors)),
+            PairQueue::Random(q) => Some((QueueKind::Random, &q.colors)),
+            PairQueue::Scripted(_) => None,
+        }
+    }
+
+    fn inner(&mut self) -> &mut dyn PieceQueue {
+        match self { PairQueue::Tsu(q) => q, PairQueue::Random(q) => q, PairQueue::Scripted(q) => q }
+    }
+}
+
+impl PieceQueue for PairQueue {
+    fn next_pair(&mut self) -> Option<Pair> { self.inner().next_pair() }
+    fn position(&self) -> usize {
+        match self { PairQueue::Tsu(q) => q.position(), PairQueue::Random(q) => q.position(), PairQueue::Scripted(q) => q.position() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_colors_are_rejected() {
+        assert_eq!(TsuQueue::new(&[], 1).err(), Some(NoColors));
+        assert_eq!(RandomQueue::new(&[], 1).err(), Some(NoColors));
+        assert!(serde_json::from_str::<TsuQueue>(r#"{"seed":1,"colors":[],"index":0}"#).is_err());
+        assert!(serde_json::from_str::<RandomQueue>(r#"{"rng":{"version":1,"state":1},"colors":[],"index":0}"#).is_err());
+        assert!(serde_json::from_str::<RandomQueue>(r#"{"rng":{"version":1,"state":1},"colors":["Red"],"index":0}"#).is_ok());
+    }
+
+    #[test]
+    fn snapshot_with_empty_colors_fails_to_load() {
+        let board = crate::Board::new(crate::Ruleset::tsu(), PuyoType::COLORS[..4].to_vec(), 3);
+        let mut json = serde_json::to_value(&board).unwrap();
+        json["queue"]["Tsu"]["colors"] = serde_json::json!([]);
+        assert!(serde_json::from_value::<crate::Board>(json).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Portable PRNG used for pair and garbage generation. A given `(version, seed)` must produce the
+/// same numbers forever, so any change to the algorithm gets a new version rather than an edit.
+///
+/// Version 1 is SplitMix64 (Steele, Lea & Flood 2014): `state += 0x9E3779B97F4A7C15`, then the
+/// output is `state` mixed by xor-shift-multiply with `0xBF58476D1CE4E5B9` and `0x94D049BB133111EB`.
+/// Ranges use Lemire's unbiased multiply-and-reject reduction.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "PuyoRngState")]
+pub struct PuyoRng { version: u32, state: u64 }
+
+#[derive(Deserialize)]
+struct PuyoRngState { version: u32, state: u64 }
+
+/// A serialized generator whose algorithm this build does not implement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownRngVersion(pub u32);
+
+impl fmt::Display for UnknownRngVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "unknown rng version {}, expected {}", self.0, PuyoRng::VERSION) }
+}
+
+impl std::error::Error for UnknownRngVersion {}
+
+impl TryFrom<PuyoRngState> for PuyoRng {
+    type Error = UnknownRngVersion;
+    fn try_from(state: PuyoRngState) -> Result<PuyoRng, UnknownRngVersion> {
+        if state.version != PuyoRng::VERSION { return Err(UnknownRngVersion(state.version)); }
+        Ok(PuyoRng { version: state.version, state: state.state })
+    }
+}
+
+impl PuyoRng {
+    pub const VERSION: u32 = 1;
+
+    pub fn new(seed: u64) -> PuyoRng { PuyoRng { version: PuyoRng::VERSION, state: seed } }
+    pub fn version(&self) -> u32 { self.version }
+    pub fn state(&self) -> u64 { self.state }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..n`, `n` must be non-zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let m = self.next_u64() as u128 * n as u128;
+            if m as u64 >= threshold { return (m >> 64) as u64; }
+        }
+    }
+
+    pub fn index(&mut self, len: usize) -> usize { self.below(len as u64) as usize }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() { items.swap(i, self.index(i + 1)); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PuyoRng;
+    use crate::{PieceQueue, PuyoType, TsuQueue};
+
+    #[test]
+    fn splitmix64_reference_values() {
+        let mut rng = PuyoRng::new(0);
+        assert_eq!([rng.next_u64(), rng.next_u64(), rng.next_u64()], [0xE220A8397B1DCDAF, 0x6E789E6AA1B965F4, 0x06C45D188009454F]);
+    }
+
+    #[test]
+    fn golden_ranges() {
+        let mut rng = PuyoRng::new(12345);
+        let values: Vec<u64> = (0..12).map(|_| rng.below(5)).collect();
+        assert_eq!(values, GOLDEN_RANGES);
+    }
+
+    #[test]
+    fn golden_palette() {
+        assert_eq!(PuyoType::palette(4, 42), GOLDEN_PALETTE);
+    }
+
+    #[test]
+    fn golden_tsu_pairs() {
+        let colors = [PuyoType::Red, PuyoType::Blue, PuyoType::Yellow, PuyoType::Green];
+        let mut queue = TsuQueue::new(&colors, 2024).unwrap();
+        let pairs: Vec<_> = (0..8).map(|_| queue.next_pair().unwrap()).collect();
+        assert_eq!(pairs, GOLDEN_TSU_PAIRS);
+    }
+
+    #[test]
+    fn serialized_state_resumes() {
+        let mut rng = PuyoRng::new(7);
+        rng.next_u64();
+        let mut copy: PuyoRng = serde_json::from_str(&serde_json::to_string(&rng).unwrap()).unwrap();
+        assert_eq!(copy.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let err = serde_json::from_str::<PuyoRng>(r#"{"version":2,"state":7}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown rng version 2"));
+    }
+
+    const GOLDEN_RANGES: [u64; 12] = [0, 1, 0, 0, 2, 1, 0, 2, 2, 4, 0, 1];
+    const GOLDEN_PALETTE: [PuyoType; 4] = [PuyoType::Blue, PuyoType::Yellow, PuyoType::Purple, PuyoType::Red];
+    const GOLDEN_TSU_PAIRS: [(PuyoType, PuyoType); 8] = [
+        (PuyoType::Blue, PuyoType::Yellow), (PuyoType::Blue, PuyoType::Blue), (PuyoType::Red, PuyoType::Yellow), (PuyoType::Yellow, PuyoType::Yellow),
+        (PuyoType::Red, PuyoType::Yellow), (PuyoType::Yellow, PuyoType::Red), (PuyoType::Green, PuyoType::Blue), (PuyoType::Yellow, PuyoType::Green),
+    ];
+}
@@ -0,0 +1,184 @@
+use crate::{PieceShape, PuyoType, QueueKind, RotationSystem};
+use serde::{Deserialize, Serialize};
+
+/// Fields missing from a ruleset file take their `Ruleset::tsu` value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Ruleset {
+    pub name: String,
+    /// `height` counts the visible rows, with `hidden_rows` above them where puyos stay but never pop
+    /// and `ghost_rows` above those where locked puyos vanish.
+    pub width: usize, pub height: usize, pub hidden_rows: usize, pub ghost_rows: usize,
+    /// Game over once any of these `(row, col)` is filled, rows counted from the top visible row.
+    pub death_cells: Vec<(usize, usize)>, pub spawn_col: usize,
+    pub pop_count: usize, pub color_count: usize,
+    pub queue: QueueKind, pub preview_len: usize,
+    /// Shapes dealt in turn, each taking its colors from the next pair.
+    pub drop_set: Vec<PieceShape>,
+    pub rotation: RotationSystem, pub quick_turn_frames: u32, pub floor_kick_limit: u32,
+    /// Timings are in frames of `1 / FRAME_RATE` seconds.
+    pub lock_frames: u32, pub lock_delay_moves: u32, pub max_ground_frames: u32,
+    pub das_delay_frames: u32, pub das_speed_frames: u32, pub soft_drop_frames: u32, pub resolve_frames: u32,
+    /// Gravity starts at `gravity_frames` per row and speeds up by `gravity_step_frames` every `level_frames`.
+    pub gravity_frames: u32, pub min_gravity_frames: u32, pub gravity_step_frames: u32, pub level_frames: u32,
+    /// `chain_powers[n - 1]` is the power of the n-th chain step, the last entry repeats.
+    pub chain_powers: Vec<u32>,
+    /// Indexed by the number of distinct colors popped minus one.
+    pub color_bonus: Vec<u32>,
+    /// Indexed by the group size minus `pop_count`.
+    pub group_bonus: Vec<u32>,
+    pub target_point: u32, pub max_garbage_drop: u32, pub all_clear_bonus: u32,
+    /// Percentage of the points turned into nuisance.
+    pub attack_percent: u32,
+    /// Margin time: from `margin_frames` on, the target point drops to `margin_percent`% of itself
+    /// every `margin_step_frames`, a step of 0 keeping it fixed. Percentages above 100 count as 100.
+    pub margin_frames: u32, pub margin_step_frames: u32, pub margin_percent: u32,
+    /// Special cells that may replace dropped nuisance, each nuisance having `special_chance` percent odds.
+    pub special_cells: Vec<PuyoType>, pub special_chance: u32,
+    /// Score per point puyo and extra nuisance per sun puyo cleared next to a pop.
+    pub point_score: u32, pub sun_garbage: u32,
+    /// Offsets needed to fill the fever gauge, 0 disables Fever. A fever lasts `fever_frames`
+    /// and its first preset chain is `fever_start_chain` long.
+    pub fever_gauge: u32, pub fever_frames: u32, pub fever_start_chain: u32,
+}
+
+impl Ruleset {
+    pub const PRESETS: [&'static str; 3] = ["tsu", "classic", "fever"];
+
+    pub fn tsu() -> Ruleset {
+        Ruleset {
+            name: "tsu".to_string(),
+            width: 6, height: 12, hidden_rows: 1, ghost_rows: 1,
+            death_cells: vec![(0, 2)], spawn_col: 2,
+            pop_count: 4, color_count: 4,
+            queue: QueueKind::Tsu, preview_len: 2, drop_set: vec![PieceShape::Pair],
+            rotation: RotationSystem::Tsu, quick_turn_frames: 20, floor_kick_limit: 8,
+            lock_frames: 30, lock_delay_moves: 15, max_ground_frames: 120,
+            das_delay_frames: 12, das_speed_frames: 3, soft_drop_frames: 3, resolve_frames: 9,
+            gravity_frames: 48, min_gravity_frames: 6, gravity_step_frames: 3, level_frames: 900,
+            chain_powers: vec![0, 8, 16, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448, 480, 512],
+            color_bonus: vec![0, 3, 6, 12, 24],
+            group_bonus: vec![0, 2, 3, 4, 5, 6, 7, 10],
+            target_point: 70, max_garbage_drop: 30, all_clear_bonus: 30, attack_percent: 100,
+            margin_frames: 5760, margin_step_frames: 960, margin_percent: 75,
+            special_cells: Vec::new(), special_chance: 0, point_score: 100, sun_garbage: 3,
+            fever_gauge: 0, fever_frames: 900, fever_start_chain: 5,
+        }
+    }
+
+    pub fn classic() -> Ruleset {
+        Ruleset {
+            name: "classic".to_string(),
+            chain_powers: vec![0, 8, 16, 32, 64, 128, 256, 512, 999],
+            target_point: 120, all_clear_bonus: 0, margin_step_frames: 0,
+            queue: QueueKind::Random, rotation: RotationSystem::Legacy,
+            ..Ruleset::tsu()
+        }
+    }
+
+    pub fn fever() -> Ruleset {
+        Ruleset {
+            name: "fever".to_string(),
+            drop_set: vec![PieceShape::Pair, PieceShape::Pair, PieceShape::TripleVertical, PieceShape::Pair, PieceShape::Quad,
+                PieceShape::Pair, PieceShape::TripleHorizontal, PieceShape::Pair, PieceShape::BigQuad],
+            fever_gauge: 7,
+            ..Ruleset::tsu()
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Ruleset> {
+        match name {
+            "tsu" => Some(Ruleset::tsu()),
+            "classic" => Some(Ruleset::classic()),
+            "fever" => Some(Ruleset::fever()),
+            _ => None,
+        }
+    }
+
+    pub fn gravity_interval(&self, level: u32) -> u32 {
+        self.gravity_frames.saturating_sub(level.saturating_sub(1).saturating_mul(self.gravity_step_frames)).max(self.min_gravity_frames)
+    }
+
+    pub fn target_point_at(&self, frame: u64) -> u32 {
+        if self.margin_step_frames == 0 || frame < self.margin_frames as u64 { return self.target_point; }
+        let steps = 1 + (frame - self.margin_frames as u64) / self.margin_step_frames as u64;
+        let percent = self.margin_percent.min(100) as u64;
+        let mut target = self.target_point as u64;
+        for _ in 0..steps {
+            if target <= 1 || percent == 100 { break; }
+            target = (target * percent / 100).max(1);
+        }
+        target as u32
+    }
+
+    pub fn chain_power(&self, chain: u32) -> u32 { lookup(&self.chain_powers, chain.saturating_sub(1) as usize) }
+    pub fn color_bonus(&self, colors: usize) -> u32 { lookup(&self.color_bonus, colors.saturating_sub(1)) }
+    pub fn group_bonus(&self, size: usize) -> u32 { lookup(&self.group_bonus, size.saturating_sub(self.pop_count)) }
+}
+
+/// Per-player adjustments on top of the match ruleset. Percentages leave things unchanged at 100,
+/// `gravity_percent` and `lock_percent` scaling frame counts so higher values are easier.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Handicap {
+    pub color_count: Option<usize>,
+    pub gravity_percent: u32, pub lock_percent: u32, pub attack_percent: u32,
+}
+
+impl Default for Handicap {
+    fn default() -> Handicap { Handicap { color_count: None, gravity_percent: 100, lock_percent: 100, attack_percent: 100 } }
+}
+
+impl Handicap {
+    pub fn apply(&self, ruleset: &Ruleset) -> Ruleset {
+        let scale = |frames: u32, percent: u32| (frames as u64 * percent as u64 / 100).clamp(1, u32::MAX as u64) as u32;
+        Ruleset {
+            color_count: self.color_count.unwrap_or(ruleset.color_count),
+            gravity_frames: scale(ruleset.gravity_frames, self.gravity_percent),
+            min_gravity_frames: scale(ruleset.min_gravity_frames, self.gravity_percent),
+            lock_frames: scale(ruleset.lock_frames, self.lock_percent),
+            max_ground_frames: scale(ruleset.max_ground_frames, self.lock_percent),
+            attack_percent: scale(ruleset.attack_percent, self.attack_percent),
+            ..ruleset.clone()
+        }
+    }
+}
+
+impl Default for Ruleset {
+    fn default() -> Ruleset { Ruleset::tsu() }
+}
+
+fn lookup(table: &[u32], idx: usize) -> u32 {
+    table.get(idx).or(table.last()).copied().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_file_keeps_defaults() {
+        let ruleset: Ruleset = serde_json::from_str(r#"{ "name": "wide", "width": 8, "target_point": 90 }"#).unwrap();
+        assert_eq!(ruleset, Ruleset { name: "wide".to_string(), width: 8, target_point: 90, ..Ruleset::tsu() });
+    }
+
+    #[test]
+    fn margin_time_steps() {
+        let ruleset = Ruleset::tsu();
+        let start = ruleset.margin_frames as u64;
+        assert_eq!(ruleset.target_point_at(start - 1), 70);
+        assert_eq!(ruleset.target_point_at(start), 52);
+        assert_eq!(ruleset.target_point_at(start + ruleset.margin_step_frames as u64 - 1), 52);
+        assert_eq!(ruleset.target_point_at(start + ruleset.margin_step_frames as u64), 39);
+        let ruleset = Ruleset { target_point: u32::MAX, margin_percent: 250, ..Ruleset::tsu() };
+        assert_eq!(ruleset.target_point_at(start + 100 * ruleset.margin_step_frames as u64), u32::MAX);
+    }
+
+    #[test]
+    fn gravity_speeds_up_without_overflow() {
+        let ruleset = Ruleset::tsu();
+        assert_eq!((ruleset.gravity_interval(1), ruleset.gravity_interval(2), ruleset.gravity_interval(100)), (48, 45, 6));
+        let ruleset: Ruleset = serde_json::from_str(r#"{ "gravity_step_frames": 4294967295, "level_frames": 1 }"#).unwrap();
+        assert_eq!(ruleset.gravity_interval(3), ruleset.min_gravity_frames);
+    }
+}
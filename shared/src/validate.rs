@@ -0,0 +1,219 @@
+use crate::{Board, GameState, PieceQueue, PuyoType, Ruleset, FIELD_MAX_HEIGHT, FIELD_MAX_WIDTH};
+use std::fmt;
+
+/// Why a board received from elsewhere cannot be used as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BoardError {
+    /// The board plays by other rules or colors than the match gives its player.
+    WrongRuleset, WrongColors,
+    /// `width`/`height` do not match the ruleset, which fixes them.
+    WrongSize { expected: (usize, usize), found: (usize, usize) },
+    WrongRowCount { expected: usize, found: usize },
+    WrongRowWidth { row: usize, expected: usize, found: usize },
+    /// Same checks for the field kept aside during Fever.
+    WrongSavedField,
+    /// A queue of another kind or colors than the ruleset and palette deal.
+    WrongQueue,
+    /// Pairs dealt by the queue do not add up to the pieces played plus the preview.
+    QueueOutOfStep { piece_count: u64, preview: usize, dealt: usize },
+    ForeignPreview { index: usize, color: PuyoType },
+    ForeignCell { row: usize, col: usize, color: PuyoType },
+    FloatingPuyo { row: usize, col: usize },
+    BadActivePiece { row: i32, col: i32, rotation: usize },
+    PieceWhileResolving(GameState),
+    FeverMismatch { state: GameState, in_fever: bool },
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoardError::WrongRuleset => write!(f, "board plays by another ruleset than the match"),
+            BoardError::WrongColors => write!(f, "board uses another palette than the match"),
+            BoardError::WrongSize { expected, found } => write!(f, "board is {}x{}, ruleset wants {}x{}", found.0, found.1, expected.0, expected.1),
+            BoardError::WrongRowCount { expected, found } => write!(f, "{} rows of cells, expected {}", found, expected),
+            BoardError::WrongRowWidth { row, expected, found } => write!(f, "row {} has {} cells, expected {}", row, found, expected),
+            BoardError::WrongSavedField => write!(f, "saved fever field has the wrong size"),
+            BoardError::WrongQueue => write!(f, "queue does not match the ruleset and palette"),
+            BoardError::QueueOutOfStep { piece_count, preview, dealt } => write!(f, "queue dealt {} pairs for {} pieces and {} previewed", dealt, piece_count, preview),
+            BoardError::ForeignPreview { index, color } => write!(f, "preview pair {} holds {:?}, outside the palette", index, color),
+            BoardError::ForeignCell { row, col, color } => write!(f, "cell ({}, {}) holds {:?}, outside the palette", row, col, color),
+            BoardError::FloatingPuyo { row, col } => write!(f, "puyo at ({}, {}) floats while a piece is in play", row, col),
+            BoardError::BadActivePiece { row, col, rotation } => write!(f, "active piece at ({}, {}) rotation {} cannot be there", row, col, rotation),
+            BoardError::PieceWhileResolving(state) => write!(f, "active piece while {:?}", state),
+            BoardError::FeverMismatch { state, in_fever } => write!(f, "state {:?} with{} a saved fever field", state, if *in_fever { "" } else { "out" }),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
+
+impl BoardError {
+    /// Floating puyos, a misplaced piece and fever state mix-ups can be fixed by `Board::repair`,
+    /// the rest leaves nothing safe to play on.
+    pub fn is_repairable(&self) -> bool {
+        matches!(self, BoardError::FloatingPuyo { .. } | BoardError::BadActivePiece { .. } | BoardError::PieceWhileResolving(_) | BoardError::FeverMismatch { .. })
+    }
+}
+
+fn check_cells(cells: &[Vec<Option<PuyoType>>], width: usize, height: usize, colors: &[PuyoType]) -> Result<(), BoardError> {
+    if cells.len() != height { return Err(BoardError::WrongRowCount { expected: height, found: cells.len() }); }
+    if let Some(row) = cells.iter().position(|row| row.len() != width) { return Err(BoardError::WrongRowWidth { row, expected: width, found: cells[row].len() }); }
+    for (row, cells) in cells.iter().enumerate() {
+        for (col, cell) in cells.iter().enumerate() {
+            if let Some(color) = cell.filter(|c| c.is_color() && !colors.contains(c)) { return Err(BoardError::ForeignCell { row, col, color }); }
+        }
+    }
+    Ok(())
+}
+
+impl Board {
+    /// Field size `with_queue` gives boards of this ruleset.
+    pub fn size_for(ruleset: &Ruleset) -> (usize, usize) {
+        let top = (ruleset.ghost_rows + ruleset.hidden_rows).min(FIELD_MAX_HEIGHT - 2);
+        (ruleset.width.clamp(3, FIELD_MAX_WIDTH), top + ruleset.height.clamp(2, FIELD_MAX_HEIGHT - top))
+    }
+
+    /// Checks a board that did not come from this process, such as a snapshot sent by the other
+    /// client, against the ruleset and palette the match gives its player, returning the first
+    /// problem found.
+    pub fn validate(&self, ruleset: &Ruleset, colors: &[PuyoType]) -> Result<(), BoardError> {
+        if self.ruleset != *ruleset { return Err(BoardError::WrongRuleset); }
+        if self.colors != colors { return Err(BoardError::WrongColors); }
+        let expected = Board::size_for(ruleset);
+        if (self.width, self.height) != expected { return Err(BoardError::WrongSize { expected, found: (self.width, self.height) }); }
+        check_cells(&self.cells, self.width, self.height, colors)?;
+        if let Some(saved) = &self.saved_cells {
+            check_cells(saved, self.width, self.height, colors).map_err(|e| if let BoardError::ForeignCell { .. } = e { e } else { BoardError::WrongSavedField })?;
+        }
+        if self.queue.generator() != Some((ruleset.queue, colors)) { return Err(BoardError::WrongQueue); }
+        let dealt = self.queue.position();
+        if self.preview.len() != ruleset.preview_len.max(1) || dealt as u64 != self.piece_count + self.preview.len() as u64 {
+            return Err(BoardError::QueueOutOfStep { piece_count: self.piece_count, preview: self.preview.len(), dealt });
+        }
+        for (index, pair) in self.preview.iter().enumerate() {
+            if let Some(color) = [pair.0, pair.1].into_iter().find(|c| !colors.contains(c)) { return Err(BoardError::ForeignPreview { index, color }); }
+        }
+
+        let settled = self.active_piece.is_some() || self.state.is_playing();
+        if settled {
+            for row in 0..self.height - 1 {
+                if let Some(col) = (0..self.width).find(|&col| self.cells[row][col].is_some() && self.cells[row + 1][col].is_none()) {
+                    return Err(BoardError::FloatingPuyo { row, col });
+                }
+            }
+        }
+        if let Some(piece) = &self.active_piece {
+            if matches!(self.state, GameState::ResolvingMatches | GameState::FeverResolving | GameState::GameOver) { return Err(BoardError::PieceWhileResolving(self.state)); }
+            if piece.rotation >= 4 || piece.row < 0 || self.check_collision(piece) {
+                return Err(BoardError::BadActivePiece { row: piece.row, col: piece.col, rotation: piece.rotation });
+            }
+        }
+        let fever_state = matches!(self.state, GameState::Fever | GameState::FeverResolving);
+        let normal_state = matches!(self.state, GameState::Playing | GameState::ResolvingMatches);
+        if (fever_state && !self.in_fever()) || (normal_state && self.in_fever()) {
+            return Err(BoardError::FeverMismatch { state: self.state, in_fever: self.in_fever() });
+        }
+        Ok(())
+    }
+
+    /// Fixes what `validate` reports, returning the problems fixed, or the first one that cannot be.
+    pub fn repair(&mut self, ruleset: &Ruleset, colors: &[PuyoType]) -> Result<Vec<BoardError>, BoardError> {
+        let mut fixed = Vec::new();
+        while let Err(error) = self.validate(ruleset, colors) {
+            if !error.is_repairable() { return Err(error); }
+            match &error {
+                BoardError::FloatingPuyo { .. } => { self.apply_board_gravity(); }
+                BoardError::BadActivePiece { .. } | BoardError::PieceWhileResolving(_) => self.active_piece = None,
+                BoardError::FeverMismatch { state, .. } => self.state = match (state, self.in_fever()) {
+                    (GameState::Playing, true) => GameState::Fever,
+                    (GameState::ResolvingMatches, true) => GameState::FeverResolving,
+                    (GameState::FeverResolving, false) => GameState::ResolvingMatches,
+                    _ => GameState::Playing,
+                },
+                _ => unreachable!(),
+            }
+            fixed.push(error);
+        }
+        Ok(fixed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PairQueue, QueueKind};
+
+    const COLORS: &[PuyoType] = &[PuyoType::Red, PuyoType::Green, PuyoType::Blue, PuyoType::Yellow];
+
+    fn board() -> Board {
+        let mut board = Board::new(Ruleset::tsu(), COLORS.to_vec(), 5);
+        board.spawn_piece();
+        board
+    }
+
+    #[test]
+    fn rejects_mismatched_size() {
+        let ruleset = Ruleset::tsu();
+        let mut board = board();
+        assert_eq!(board.validate(&ruleset, COLORS), Ok(()));
+        board.cells.pop();
+        assert_eq!(board.validate(&ruleset, COLORS), Err(BoardError::WrongRowCount { expected: 14, found: 13 }));
+        assert!(board.repair(&ruleset, COLORS).is_err());
+        let mut board = self::board();
+        board.width = 40;
+        assert_eq!(board.validate(&ruleset, COLORS), Err(BoardError::WrongSize { expected: (6, 14), found: (40, 14) }));
+    }
+
+    #[test]
+    fn rejects_another_ruleset_or_palette() {
+        let board = board();
+        let wide = Ruleset { width: 8, ..Ruleset::tsu() };
+        assert_eq!(board.validate(&wide, COLORS), Err(BoardError::WrongRuleset));
+        assert_eq!(board.validate(&Ruleset::tsu(), &COLORS[..3]), Err(BoardError::WrongColors));
+        let mut board = self::board();
+        board.ruleset = wide.clone();
+        board.width = 8;
+        board.cells.iter_mut().for_each(|row| row.resize(8, None));
+        assert_eq!(board.validate(&wide, COLORS), Ok(()), "a snapshot matching its player's settings is fine");
+        assert_eq!(board.validate(&Ruleset::tsu(), COLORS), Err(BoardError::WrongRuleset));
+    }
+
+    #[test]
+    fn rejects_foreign_colors() {
+        let ruleset = Ruleset::tsu();
+        let mut board = board();
+        board.cells[13][2] = Some(PuyoType::Purple);
+        assert_eq!(board.validate(&ruleset, COLORS), Err(BoardError::ForeignCell { row: 13, col: 2, color: PuyoType::Purple }));
+        assert!(board.repair(&ruleset, COLORS).is_err());
+        let mut board = self::board();
+        board.cells[13][2] = Some(PuyoType::Garbage);
+        assert_eq!(board.validate(&ruleset, COLORS), Ok(()), "garbage is not a palette color");
+        board.preview[1].1 = PuyoType::Purple;
+        assert_eq!(board.validate(&ruleset, COLORS), Err(BoardError::ForeignPreview { index: 1, color: PuyoType::Purple }));
+    }
+
+    #[test]
+    fn rejects_queue_out_of_step() {
+        let ruleset = Ruleset::tsu();
+        let mut board = board();
+        board.piece_count += 1;
+        assert_eq!(board.validate(&ruleset, COLORS), Err(BoardError::QueueOutOfStep { piece_count: 2, preview: 2, dealt: 3 }));
+        let mut board = self::board();
+        board.queue = PairQueue::new(QueueKind::Random, COLORS, 5).unwrap();
+        assert_eq!(board.validate(&ruleset, COLORS), Err(BoardError::WrongQueue));
+    }
+
+    #[test]
+    fn repairs_floating_puyos_and_bad_piece() {
+        let ruleset = Ruleset::tsu();
+        let mut board = board();
+        board.cells[5][0] = Some(PuyoType::Red);
+        board.active_piece.as_mut().unwrap().col = -3;
+        assert_eq!(board.validate(&ruleset, COLORS), Err(BoardError::FloatingPuyo { row: 5, col: 0 }));
+        let fixed = board.repair(&ruleset, COLORS).unwrap();
+        assert_eq!(fixed.len(), 2);
+        assert_eq!(board.cells[13][0], Some(PuyoType::Red));
+        assert!(board.active_piece.is_none());
+        assert_eq!(board.validate(&ruleset, COLORS), Ok(()));
+    }
+}